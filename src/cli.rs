@@ -0,0 +1,155 @@
+//! Command line interface, and the one-off commands that don't run the server.
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use clap::{Parser, Subcommand};
+use color_eyre::eyre::{Result, WrapErr, bail, eyre};
+use tokio::sync::watch;
+use tracing::info;
+
+use usync_core::config::Config;
+use usync_core::updater::{self, parse_header};
+use usync_core::{SharedState, Webhook, new_sync_id, parse_event, resolve_head};
+
+#[derive(Parser)]
+#[command(version, about)]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Receive webhooks and deploy pushes (the default)
+    Serve,
+    /// Deploy a synced page from its branch head once, then exit
+    Sync {
+        title: String,
+        /// wiki the page is on, by config name. May be left out with a single wiki
+        #[arg(long)]
+        wiki: Option<String>,
+    },
+    /// Check the sync header of a local file
+    CheckHeader { file: PathBuf },
+    /// Feed a saved webhook payload through the pipeline, then exit
+    Replay {
+        payload: PathBuf,
+        /// the `X-GitHub-Event` the payload was delivered with
+        #[arg(long, default_value = "push")]
+        event: String,
+        /// check what would be deployed without editing anything
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+/// Content model a file would have on-wiki, going by its extension.
+fn contentmodel(file: &Path) -> &'static str {
+    match file.extension().and_then(|e| e.to_str()) {
+        Some("css") => "css",
+        Some("json") => "json",
+        Some("lua") => "Scribunto",
+        _ => "javascript",
+    }
+}
+
+pub fn check_header(config: &Config, file: &Path) -> Result<()> {
+    let text = std::fs::read_to_string(file).wrap_err_with(|| eyre!("{}", file.display()))?;
+    let Some(header) = parse_header(contentmodel(file), &text) else {
+        bail!("no valid sync header in {}", file.display());
+    };
+
+    println!("repo:     {}", header.repo);
+    println!("ref:      {}", header.ref_);
+    println!("path:     {}", header.path);
+    if let Some(artifact) = &header.artifact {
+        println!("artifact: {artifact}");
+    }
+    println!("comment:  {}", header.comment);
+
+    let mut accepted = Vec::new();
+    for wiki in config.wikis.keys() {
+        match config.check_wiki(&header.wikis, wiki) {
+            Ok(()) => accepted.push(&**wiki),
+            // an unknown wiki is wrong wherever the page goes
+            Err(reason) if reason.starts_with("unknown") => bail!(reason),
+            Err(_) => {}
+        }
+    }
+    if accepted.is_empty() {
+        bail!("none of the configured wikis would accept this header");
+    }
+    println!("wikis:    {}", accepted.join(", "));
+    Ok(())
+}
+
+pub async fn sync(
+    ss: Arc<SharedState>,
+    mut rebuilt: watch::Receiver<()>,
+    wiki: Option<String>,
+    title: String,
+) -> Result<()> {
+    let wiki = match wiki {
+        Some(wiki) => wiki,
+        None => {
+            let config = ss.config();
+            let mut wikis = config.wikis.keys();
+            match (wikis.next(), wikis.next()) {
+                (Some(wiki), None) => wiki.clone(),
+                _ => bail!("more than one wiki is configured, pick one with --wiki"),
+            }
+        }
+    };
+
+    let _ = rebuilt.changed().await;
+    let Some((source, target)) = ss.resolve_mapping(&wiki, &title).await else {
+        bail!("{title} on {wiki} isn't synced from anywhere");
+    };
+    info!(repo = %source.repo, ref_ = %source.ref_, path = %target.path, "syncing");
+    if !updater::force_sync(ss, source, target).await {
+        bail!("sync failed");
+    }
+    Ok(())
+}
+
+pub async fn replay(
+    ss: Arc<SharedState>,
+    mut rebuilt: watch::Receiver<()>,
+    event: &str,
+    payload: &Path,
+    dry_run: bool,
+) -> Result<()> {
+    let body = std::fs::read_to_string(payload).wrap_err_with(|| eyre!("{}", payload.display()))?;
+    let mut push = match parse_event(event, &body).wrap_err("invalid payload")? {
+        Webhook::Push(push) => *push,
+        Webhook::Moved(from, to) => bail!("{from} was moved to {to}, nothing to deploy"),
+        Webhook::Ignored => bail!("this {event} event wouldn't deploy anything"),
+    };
+    if push.after.is_empty() && !resolve_head(&ss, &mut push).await {
+        bail!("couldn't tell which commit to deploy");
+    }
+    push.sync_id = new_sync_id();
+    push.dry_run = dry_run;
+
+    let _ = rebuilt.changed().await;
+    let targets = updater::targets(&ss, &push);
+    if targets.is_empty() {
+        bail!(
+            "nothing is synced from {} at {}",
+            push.repository.html_url,
+            push.ref_
+        );
+    }
+    if !updater::deploy(ss, push, targets).await {
+        bail!("some pages failed to sync");
+    }
+    Ok(())
+}
+
+#[test]
+fn test_contentmodel() {
+    assert_eq!(contentmodel(Path::new("src/gadget.js")), "javascript");
+    assert_eq!(contentmodel(Path::new("styles.css")), "css");
+    assert_eq!(contentmodel(Path::new("Module.lua")), "Scribunto");
+}
@@ -0,0 +1,286 @@
+use actix_web::{HttpRequest, HttpResponse, Responder, delete, get, post, web};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::{error, info, warn};
+
+use crate::parser::{Reparse, SyncSource};
+use crate::updater;
+use crate::{SharedState, State};
+
+/// Whether the request carries the admin token. Without a configured token, nobody is.
+fn authorized(ss: &SharedState, req: &HttpRequest) -> bool {
+    let Some(token) = &ss.admin_token else {
+        return false;
+    };
+    req.headers()
+        .get("Authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        == Some(token.as_str())
+}
+
+#[get("/admin/failed")]
+pub async fn failed(state: web::Data<State>, req: HttpRequest) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match ss.audit.failed() {
+        Ok(failed) => HttpResponse::Ok().json(json!({ "failed": failed })),
+        Err(e) => {
+            error!(?e, "couldn't list failed syncs");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/admin/failed/{id}/retry")]
+pub async fn retry(
+    state: web::Data<State>,
+    req: HttpRequest,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match ss.audit.take_failed(*id) {
+        Ok(Some(sync)) => {
+            crate::updater::retry(ss.clone(), sync.push, sync.wiki, sync.target);
+            HttpResponse::Accepted().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!(?e, "couldn't load failed sync");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Deploy a held sync after all, however much it shrinks the page.
+#[post("/admin/failed/{id}/confirm")]
+pub async fn confirm(
+    state: web::Data<State>,
+    req: HttpRequest,
+    id: web::Path<i64>,
+) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match ss.audit.take_failed(*id) {
+        Ok(Some(mut sync)) => {
+            info!(id = *id, wiki = %sync.wiki, title = %sync.target.title, "confirmed held sync");
+            sync.push.confirmed = true;
+            crate::updater::retry(ss.clone(), sync.push, sync.wiki, sync.target);
+            HttpResponse::Accepted().finish()
+        }
+        Ok(None) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!(?e, "couldn't load held sync");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[post("/admin/reparse")]
+pub async fn reparse(state: web::Data<State>, req: HttpRequest) -> impl Responder {
+    if !authorized(&state.shared, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match state.reparse.try_send(Reparse::All) {
+        Ok(()) => HttpResponse::Accepted().finish(),
+        Err(_) => HttpResponse::ServiceUnavailable().finish(),
+    }
+}
+
+/// Reload the config without restarting, like SIGHUP.
+#[post("/admin/reload")]
+pub async fn reload(state: web::Data<State>, req: HttpRequest) -> impl Responder {
+    if !authorized(&state.shared, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match state.shared.reload_config() {
+        Ok(()) => {
+            info!("reloaded config");
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => HttpResponse::BadRequest().body(format!("{e:#}")),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct PageQuery {
+    wiki: String,
+    title: String,
+}
+
+/// Deploy a mapped page from its branch head, even if we think it's up to date.
+#[post("/admin/sync")]
+pub async fn force_sync(
+    state: web::Data<State>,
+    req: HttpRequest,
+    query: web::Query<PageQuery>,
+) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let PageQuery { wiki, title } = query.into_inner();
+    let Some((source, target)) = ss.resolve_mapping(&wiki, &title).await else {
+        return HttpResponse::NotFound().finish();
+    };
+
+    tokio::spawn(updater::force_sync(ss.clone(), source, target));
+    HttpResponse::Accepted().finish()
+}
+
+#[derive(Deserialize)]
+pub struct MappingQuery {
+    wiki: String,
+    /// `owner/name` or the repository URL
+    repo: String,
+    /// a branch name or a full ref
+    #[serde(rename = "ref")]
+    ref_: String,
+    title: String,
+    reason: Option<String>,
+}
+
+impl MappingQuery {
+    fn source(&self, ss: &SharedState) -> SyncSource {
+        let repo = match self.repo.contains("://") {
+            true => self.repo.trim_end_matches('/').to_owned(),
+            false => format!("https://github.com/{}", self.repo.trim_matches('/')),
+        };
+        let ref_ = match self.ref_.starts_with("refs/") {
+            true => self.ref_.clone(),
+            false => format!("refs/heads/{}", self.ref_),
+        };
+        SyncSource {
+            wiki: self.wiki.clone(),
+            repo: ss.canonical_repo(&repo),
+            ref_,
+        }
+    }
+
+    /// Whether this names the page `title` synced from `source`. Repository names and the first
+    /// letter of titles don't care about case.
+    fn matches(&self, ss: &SharedState, source: &SyncSource, title: &str) -> bool {
+        let wanted = self.source(ss);
+        source.wiki == wanted.wiki
+            && source.repo.eq_ignore_ascii_case(&wanted.repo)
+            && source.ref_ == wanted.ref_
+            && title_key(title) == title_key(&self.title)
+    }
+}
+
+/// A title as MediaWiki tells titles apart.
+fn title_key(title: &str) -> String {
+    let title = title.trim().replace('_', " ");
+    let mut chars = title.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[test]
+fn test_title_key() {
+    assert_eq!(title_key("user:Foo_bar.js"), "User:Foo bar.js");
+    assert_eq!(title_key(" MediaWiki:Common.css"), "MediaWiki:Common.css");
+    assert_eq!(title_key(""), "");
+}
+
+/// Stop syncing a page from a branch, even while its header still asks for it. Meant for
+/// emergencies like a compromised repository; the block outlives restarts.
+#[delete("/admin/mappings")]
+pub async fn block(
+    state: web::Data<State>,
+    req: HttpRequest,
+    query: web::Query<MappingQuery>,
+) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    // a typo shouldn't pass for a successful block
+    let mapped = ss.map.load().iter().find_map(|(source, targets)| {
+        let target = targets
+            .iter()
+            .find(|t| query.matches(ss, source, &t.title))?;
+        Some((source.clone(), target.title.clone()))
+    });
+    let Some((source, title)) = mapped else {
+        return HttpResponse::NotFound().finish();
+    };
+    match ss.audit.block(&source, &title, query.reason.as_deref()) {
+        Ok(()) => {
+            warn!(?source, %title, reason = ?query.reason, "blocked mapping");
+            HttpResponse::NoContent().finish()
+        }
+        Err(e) => {
+            error!(?e, "couldn't block mapping");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+#[get("/admin/blocked")]
+pub async fn blocked(state: web::Data<State>, req: HttpRequest) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    match ss.audit.blocked() {
+        Ok(blocked) => HttpResponse::Ok().json(json!({ "blocked": blocked })),
+        Err(e) => {
+            error!(?e, "couldn't list blocked mappings");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Lift a block, so the page syncs again from the next push.
+#[delete("/admin/blocked")]
+pub async fn unblock(
+    state: web::Data<State>,
+    req: HttpRequest,
+    query: web::Query<MappingQuery>,
+) -> impl Responder {
+    let ss = &state.shared;
+    if !authorized(ss, &req) {
+        return HttpResponse::Unauthorized().finish();
+    }
+    let entries = match ss.audit.blocked() {
+        Ok(entries) => entries,
+        Err(e) => {
+            error!(?e, "couldn't list blocked mappings");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+    let found = entries.into_iter().find_map(|b| {
+        let source = SyncSource {
+            wiki: b.wiki,
+            repo: b.repo,
+            ref_: b.ref_,
+        };
+        query
+            .matches(ss, &source, &b.title)
+            .then_some((source, b.title))
+    });
+    let Some((source, title)) = found else {
+        return HttpResponse::NotFound().finish();
+    };
+    match ss.audit.unblock(&source, &title) {
+        Ok(true) => {
+            info!(?source, %title, "unblocked mapping");
+            HttpResponse::NoContent().finish()
+        }
+        Ok(false) => HttpResponse::NotFound().finish(),
+        Err(e) => {
+            error!(?e, "couldn't unblock mapping");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
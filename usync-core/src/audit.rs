@@ -0,0 +1,526 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use color_eyre::eyre::Result;
+use rusqlite::{Connection, OptionalExtension, Row, params};
+use serde::{Deserialize, Serialize};
+
+use crate::GitHubPush;
+use crate::config::Config;
+use crate::parser::{SyncSource, Target};
+
+/// Durable record of every sync attempt, kept in `state_dir/audit.sqlite`.
+pub struct Audit {
+    conn: Mutex<Connection>,
+}
+
+/// A file synced to a page.
+pub struct Mapping<'a> {
+    pub wiki: &'a str,
+    pub repo: &'a str,
+    pub ref_: &'a str,
+    pub path: &'a str,
+    pub title: &'a str,
+}
+
+/// One sync attempt of a file to a page.
+pub struct Attempt<'a> {
+    pub wiki: &'a str,
+    pub repo: &'a str,
+    pub ref_: &'a str,
+    pub path: &'a str,
+    pub title: &'a str,
+    pub sha: &'a str,
+    pub sync_id: &'a str,
+    /// `deployed`, `staged`, `nochange`, `skipped`, `drifted`, `gone`, `removed`, `held`,
+    /// `blocked`, `failure` or `error`. Staged deployments are recorded against the staging page
+    pub result: &'a str,
+    pub revid: Option<u64>,
+    pub error: Option<&'a str>,
+    /// the edit summary, for deployments
+    pub summary: Option<&'a str>,
+    /// how often fetching from GitHub was retried after transient failures
+    pub fetch_retries: u32,
+}
+
+/// The latest attempt at syncing a page.
+pub struct LastAttempt {
+    pub timestamp: String,
+    pub sha: String,
+    pub result: String,
+    pub error: Option<String>,
+}
+
+/// What to look for in past sync attempts. Anything left out matches everything.
+#[derive(Deserialize, Default)]
+#[serde(deny_unknown_fields)]
+pub struct HistoryQuery {
+    pub wiki: Option<String>,
+    pub title: Option<String>,
+    pub repo: Option<String>,
+    /// a commit, or the start of one
+    pub sha: Option<String>,
+    /// attempts at or after this time, e.g. `2025-01-02` or `2025-01-02T03:04:05Z`
+    pub since: Option<String>,
+    /// most attempts to return, newest first
+    pub limit: Option<usize>,
+}
+
+/// Most attempts a history query returns.
+const HISTORY_LIMIT: usize = 1000;
+
+/// A past sync attempt.
+#[derive(Serialize)]
+pub struct HistoryEntry {
+    pub timestamp: String,
+    pub sync_id: Option<String>,
+    pub wiki: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+    pub path: String,
+    pub title: String,
+    pub sha: String,
+    pub result: String,
+    pub revid: Option<u64>,
+    pub error: Option<String>,
+}
+
+/// A successful deployment from the log.
+pub struct Deploy {
+    pub id: i64,
+    pub timestamp: String,
+    pub wiki: String,
+    pub repo: String,
+    pub title: String,
+    pub sha: String,
+    pub revid: Option<u64>,
+    pub summary: Option<String>,
+}
+
+/// A mapping an admin stopped syncing.
+#[derive(Serialize)]
+pub struct Blocked {
+    pub wiki: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+    pub title: String,
+    pub timestamp: String,
+    pub reason: Option<String>,
+}
+
+/// A sync that gave up.
+#[derive(Serialize)]
+pub struct Failed {
+    pub id: i64,
+    pub timestamp: String,
+    pub wiki: String,
+    pub target: Target,
+    #[serde(skip)]
+    pub push: GitHubPush,
+    /// the pushed commit
+    pub sha: String,
+    pub reason: String,
+}
+
+fn failed_row(row: &Row<'_>) -> rusqlite::Result<Result<Failed>> {
+    let target: String = row.get(3)?;
+    let push: String = row.get(4)?;
+    let parse = || -> Result<Failed> {
+        let push: GitHubPush = serde_json::from_str(&push)?;
+        Ok(Failed {
+            id: row.get(0)?,
+            timestamp: row.get(1)?,
+            wiki: row.get(2)?,
+            target: serde_json::from_str(&target)?,
+            sha: push.after.clone(),
+            push,
+            reason: row.get(5)?,
+        })
+    };
+    Ok(parse())
+}
+
+/// Schema changes since the tables were introduced, applied in order and tracked through
+/// `user_version`.
+const MIGRATIONS: &[&str] = &[
+    "ALTER TABLE attempts ADD COLUMN sync_id TEXT;",
+    "ALTER TABLE attempts ADD COLUMN summary TEXT;",
+    "ALTER TABLE attempts ADD COLUMN fetch_retries INTEGER NOT NULL DEFAULT 0;",
+    "CREATE TABLE panics (
+        id INTEGER PRIMARY KEY,
+        timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+        sync_id TEXT NOT NULL,
+        push TEXT NOT NULL,
+        message TEXT NOT NULL
+    );",
+    "CREATE TABLE blocked (
+        wiki TEXT NOT NULL,
+        repo TEXT NOT NULL,
+        ref TEXT NOT NULL,
+        title TEXT NOT NULL,
+        timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+        reason TEXT,
+        PRIMARY KEY (wiki, repo, ref, title)
+    );",
+];
+
+fn migrate(conn: &Connection) -> Result<()> {
+    let version: u32 = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, migration) in (1..).zip(MIGRATIONS).skip(version as usize) {
+        conn.execute_batch(migration)?;
+        conn.pragma_update(None, "user_version", version)?;
+    }
+    Ok(())
+}
+
+impl Audit {
+    pub fn open(config: &Config) -> Result<Self> {
+        let conn = Connection::open(config.state_dir.join("audit.sqlite"))?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS attempts (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                wiki TEXT NOT NULL,
+                repo TEXT NOT NULL,
+                ref TEXT NOT NULL,
+                path TEXT NOT NULL,
+                title TEXT NOT NULL,
+                sha TEXT NOT NULL,
+                result TEXT NOT NULL,
+                revid INTEGER,
+                error TEXT
+            );
+            CREATE INDEX IF NOT EXISTS attempts_title ON attempts (wiki, title);
+            CREATE TABLE IF NOT EXISTS failed (
+                id INTEGER PRIMARY KEY,
+                timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                wiki TEXT NOT NULL,
+                target TEXT NOT NULL,
+                push TEXT NOT NULL,
+                reason TEXT NOT NULL
+            );
+            CREATE TABLE IF NOT EXISTS last_synced (
+                wiki TEXT NOT NULL,
+                repo TEXT NOT NULL,
+                ref TEXT NOT NULL,
+                path TEXT NOT NULL,
+                title TEXT NOT NULL,
+                sha TEXT NOT NULL,
+                PRIMARY KEY (wiki, repo, ref, path, title)
+            );
+            CREATE TABLE IF NOT EXISTS inflight (
+                sync_id TEXT PRIMARY KEY,
+                timestamp TEXT NOT NULL DEFAULT (strftime('%Y-%m-%dT%H:%M:%SZ', 'now')),
+                push TEXT NOT NULL
+            );",
+        )?;
+        migrate(&conn)?;
+        Ok(Audit {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// The last commit confirmed on a page for a mapping.
+    pub fn last_synced(&self, mapping: &Mapping<'_>) -> Result<Option<String>> {
+        let sha = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT sha FROM last_synced
+                WHERE wiki = ?1 AND repo = ?2 AND ref = ?3 AND path = ?4 AND title = ?5",
+                params![
+                    mapping.wiki,
+                    mapping.repo,
+                    mapping.ref_,
+                    mapping.path,
+                    mapping.title
+                ],
+                |row| row.get(0),
+            )
+            .optional()?;
+        Ok(sha)
+    }
+
+    pub fn set_last_synced(&self, mapping: &Mapping<'_>, sha: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO last_synced (wiki, repo, ref, path, title, sha)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6)
+            ON CONFLICT DO UPDATE SET sha = excluded.sha",
+            params![
+                mapping.wiki,
+                mapping.repo,
+                mapping.ref_,
+                mapping.path,
+                mapping.title,
+                sha
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Stop syncing a page from a branch, whatever its header says.
+    pub fn block(&self, source: &SyncSource, title: &str, reason: Option<&str>) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO blocked (wiki, repo, ref, title, reason) VALUES (?1, ?2, ?3, ?4, ?5)
+            ON CONFLICT DO UPDATE SET reason = excluded.reason",
+            params![source.wiki, source.repo, source.ref_, title, reason],
+        )?;
+        Ok(())
+    }
+
+    /// Sync a blocked page again. Returns whether it was blocked.
+    pub fn unblock(&self, source: &SyncSource, title: &str) -> Result<bool> {
+        let removed = self.conn.lock().unwrap().execute(
+            "DELETE FROM blocked WHERE wiki = ?1 AND repo = ?2 AND ref = ?3 AND title = ?4",
+            params![source.wiki, source.repo, source.ref_, title],
+        )?;
+        Ok(removed > 0)
+    }
+
+    pub fn is_blocked(&self, source: &SyncSource, title: &str) -> Result<bool> {
+        let blocked = self
+            .conn
+            .lock()
+            .unwrap()
+            .query_row(
+                "SELECT 1 FROM blocked WHERE wiki = ?1 AND repo = ?2 AND ref = ?3 AND title = ?4",
+                params![source.wiki, source.repo, source.ref_, title],
+                |_| Ok(()),
+            )
+            .optional()?;
+        Ok(blocked.is_some())
+    }
+
+    pub fn blocked(&self) -> Result<Vec<Blocked>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT wiki, repo, ref, title, timestamp, reason FROM blocked ORDER BY timestamp",
+        )?;
+        let rows = stmt.query_map([], |row| {
+            Ok(Blocked {
+                wiki: row.get(0)?,
+                repo: row.get(1)?,
+                ref_: row.get(2)?,
+                title: row.get(3)?,
+                timestamp: row.get(4)?,
+                reason: row.get(5)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Keep a sync that gave up, so it can be retried later.
+    pub fn add_failed(
+        &self,
+        wiki: &str,
+        target: &Target,
+        push: &GitHubPush,
+        reason: &str,
+    ) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO failed (wiki, target, push, reason) VALUES (?1, ?2, ?3, ?4)",
+            params![
+                wiki,
+                serde_json::to_string(target)?,
+                serde_json::to_string(push)?,
+                reason
+            ],
+        )?;
+        Ok(())
+    }
+
+    /// Keep the push a sync panicked on, along with the panic message.
+    pub fn add_panic(&self, push: &GitHubPush, message: &str) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO panics (sync_id, push, message) VALUES (?1, ?2, ?3)",
+            params![push.sync_id, serde_json::to_string(push)?, message],
+        )?;
+        Ok(())
+    }
+
+    /// Journal an accepted push until it's done with, so a restart can pick it up again.
+    pub fn begin(&self, push: &GitHubPush) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT OR REPLACE INTO inflight (sync_id, push) VALUES (?1, ?2)",
+            params![push.sync_id, serde_json::to_string(push)?],
+        )?;
+        Ok(())
+    }
+
+    /// Drop a push from the journal, once every sync for it has finished or given up.
+    pub fn finish(&self, sync_id: &str) -> Result<()> {
+        self.conn
+            .lock()
+            .unwrap()
+            .execute("DELETE FROM inflight WHERE sync_id = ?1", [sync_id])?;
+        Ok(())
+    }
+
+    /// Pushes a previous run accepted but didn't finish, oldest first.
+    pub fn unfinished(&self) -> Result<Vec<GitHubPush>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare("SELECT push FROM inflight ORDER BY timestamp, rowid")?;
+        let rows = stmt.query_map([], |row| row.get::<_, String>(0))?;
+        let mut pushes = Vec::new();
+        for row in rows {
+            pushes.push(serde_json::from_str(&row?)?);
+        }
+        Ok(pushes)
+    }
+
+    pub fn failed(&self) -> Result<Vec<Failed>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn
+            .prepare("SELECT id, timestamp, wiki, target, push, reason FROM failed ORDER BY id")?;
+        let rows = stmt.query_map([], failed_row)?;
+        let mut failed = Vec::new();
+        for row in rows {
+            failed.push(row??);
+        }
+        Ok(failed)
+    }
+
+    /// Remove a failed sync from the store, returning it.
+    pub fn take_failed(&self, id: i64) -> Result<Option<Failed>> {
+        let conn = self.conn.lock().unwrap();
+        let failed = conn
+            .query_row(
+                "SELECT id, timestamp, wiki, target, push, reason FROM failed WHERE id = ?1",
+                [id],
+                failed_row,
+            )
+            .optional()?
+            .transpose()?;
+        conn.execute("DELETE FROM failed WHERE id = ?1", [id])?;
+        Ok(failed)
+    }
+
+    /// When a page on a wiki was last confirmed in sync.
+    pub fn last_success(&self, wiki: &str) -> Result<Option<String>> {
+        let timestamp = self.conn.lock().unwrap().query_row(
+            "SELECT MAX(timestamp) FROM attempts
+            WHERE wiki = ?1 AND result IN ('deployed', 'nochange')",
+            [wiki],
+            |row| row.get(0),
+        )?;
+        Ok(timestamp)
+    }
+
+    /// The latest attempt for every page, by (wiki, title).
+    pub fn last_attempts(&self) -> Result<HashMap<(String, String), LastAttempt>> {
+        self.latest("SELECT MAX(id) FROM attempts GROUP BY wiki, title")
+    }
+
+    /// The latest failed attempt for every page that ever failed, by (wiki, title).
+    pub fn last_errors(&self) -> Result<HashMap<(String, String), LastAttempt>> {
+        self.latest("SELECT MAX(id) FROM attempts WHERE error IS NOT NULL GROUP BY wiki, title")
+    }
+
+    fn latest(&self, ids: &str) -> Result<HashMap<(String, String), LastAttempt>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(&format!(
+            "SELECT wiki, title, timestamp, sha, result, error FROM attempts WHERE id IN ({ids})"
+        ))?;
+        let rows = stmt.query_map([], |row| {
+            Ok((
+                (row.get(0)?, row.get(1)?),
+                LastAttempt {
+                    timestamp: row.get(2)?,
+                    sha: row.get(3)?,
+                    result: row.get(4)?,
+                    error: row.get(5)?,
+                },
+            ))
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// The latest deployments, newest first.
+    pub fn recent_deploys(&self, limit: usize) -> Result<Vec<Deploy>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT id, timestamp, wiki, repo, title, sha, revid, summary FROM attempts
+            WHERE result = 'deployed' ORDER BY id DESC LIMIT ?1",
+        )?;
+        let rows = stmt.query_map([limit as i64], |row| {
+            Ok(Deploy {
+                id: row.get(0)?,
+                timestamp: row.get(1)?,
+                wiki: row.get(2)?,
+                repo: row.get(3)?,
+                title: row.get(4)?,
+                sha: row.get(5)?,
+                revid: row.get::<_, Option<i64>>(6)?.map(|r| r as u64),
+                summary: row.get(7)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    /// Past attempts matching a query, newest first.
+    pub fn history(&self, query: &HistoryQuery) -> Result<Vec<HistoryEntry>> {
+        let conn = self.conn.lock().unwrap();
+        let mut stmt = conn.prepare(
+            "SELECT timestamp, sync_id, wiki, repo, ref, path, title, sha, result, revid, error
+            FROM attempts
+            WHERE (?1 IS NULL OR wiki = ?1)
+                AND (?2 IS NULL OR title = ?2)
+                AND (?3 IS NULL OR repo = ?3)
+                AND (?4 IS NULL OR substr(sha, 1, length(?4)) = ?4)
+                AND (?5 IS NULL OR timestamp >= ?5)
+            ORDER BY id DESC LIMIT ?6",
+        )?;
+        let limit = query.limit.unwrap_or(100).min(HISTORY_LIMIT);
+        let params = params![
+            query.wiki,
+            query.title,
+            query.repo,
+            query.sha,
+            query.since,
+            limit as i64,
+        ];
+        let rows = stmt.query_map(params, |row| {
+            Ok(HistoryEntry {
+                timestamp: row.get(0)?,
+                sync_id: row.get(1)?,
+                wiki: row.get(2)?,
+                repo: row.get(3)?,
+                ref_: row.get(4)?,
+                path: row.get(5)?,
+                title: row.get(6)?,
+                sha: row.get(7)?,
+                result: row.get(8)?,
+                revid: row.get::<_, Option<i64>>(9)?.map(|r| r as u64),
+                error: row.get(10)?,
+            })
+        })?;
+        Ok(rows.collect::<rusqlite::Result<_>>()?)
+    }
+
+    pub fn record(&self, attempt: &Attempt<'_>) -> Result<()> {
+        self.conn.lock().unwrap().execute(
+            "INSERT INTO attempts
+            (wiki, repo, ref, path, title, sha, result, revid, error, sync_id, summary,
+             fetch_retries)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                attempt.wiki,
+                attempt.repo,
+                attempt.ref_,
+                attempt.path,
+                attempt.title,
+                attempt.sha,
+                attempt.result,
+                attempt.revid.map(|r| r as i64),
+                attempt.error,
+                attempt.sync_id,
+                attempt.summary,
+                attempt.fetch_retries,
+            ],
+        )?;
+        Ok(())
+    }
+}
@@ -0,0 +1,125 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, eyre};
+use reqwest::header::{AUTHORIZATION, HeaderMap, HeaderValue};
+use serde::Deserialize;
+use tracing::error;
+use tracing::info;
+
+use crate::SharedState;
+use crate::config::Config;
+
+/// How to log in to a wiki.
+#[derive(Deserialize, Clone)]
+#[serde(untagged)]
+pub enum Credential {
+    /// an owner-only OAuth 2 access token
+    OAuthToken { oauth_token: String },
+    /// an OAuth 2 client, exchanged for short-lived access tokens
+    ClientCredentials {
+        client_id: String,
+        client_secret: String,
+    },
+}
+
+#[derive(Deserialize)]
+pub struct Secrets {
+    /// used for wikis without an entry of their own
+    #[serde(flatten)]
+    default: Option<Credential>,
+    #[serde(default)]
+    wikis: HashMap<String, Credential>,
+    /// for GitHub API calls that need more than anonymous access
+    pub github_token: Option<String>,
+    /// bearer token for the `/admin` endpoints, which are disabled without one
+    pub admin_token: Option<String>,
+    /// webhook URL warnings and errors are posted to
+    pub discord_webhook: Option<String>,
+}
+
+impl Secrets {
+    pub fn credential(&self, wiki: &str) -> Result<&Credential> {
+        self.wikis
+            .get(wiki)
+            .or(self.default.as_ref())
+            .ok_or_else(|| eyre!("no credentials for {wiki}"))
+    }
+}
+
+#[derive(Deserialize)]
+struct AccessToken {
+    access_token: String,
+    expires_in: u64,
+}
+
+/// Log in to the wiki, returning the client, the user we are logged in as,
+/// and how long its access token lasts.
+pub async fn login(
+    config: &Config,
+    wiki: &str,
+    cred: &Credential,
+) -> Result<(w::Client, String, Option<Duration>)> {
+    let api = &config.wikis[wiki].api;
+    let (token, expires_in) = match cred {
+        Credential::OAuthToken { oauth_token } => (oauth_token.clone(), None),
+        Credential::ClientCredentials {
+            client_id,
+            client_secret,
+        } => {
+            // e.g. https://en.wikipedia.org/w/rest.php/oauth2/access_token
+            let url = format!("{}/oauth2/access_token", api.replace("api.php", "rest.php"));
+            let res = reqwest::Client::builder()
+                .user_agent(&config.user_agent)
+                .build()?
+                .post(url)
+                .form(&[
+                    ("grant_type", "client_credentials"),
+                    ("client_id", client_id),
+                    ("client_secret", client_secret),
+                ])
+                .send()
+                .await?
+                .error_for_status()?
+                .json::<AccessToken>()
+                .await?;
+            (res.access_token, Some(Duration::from_secs(res.expires_in)))
+        }
+    };
+
+    // same as `login_oauth`, but the builder isn't `Send` so it can't be held across the await
+    let mut headers = HeaderMap::new();
+    headers.insert(
+        AUTHORIZATION,
+        HeaderValue::from_str(&format!("Bearer {token}"))?,
+    );
+    let client = w::ClientBuilder::new(api)
+        .user_agent(config.user_agent.clone())
+        .configure_client(Box::new(move |b| b.default_headers(headers)))
+        .anonymous()?;
+    let user = client.verify_logged_in().await?;
+    info!(%wiki, %user, "logged in");
+
+    Ok((client, user, expires_in))
+}
+
+/// Keep exchanging client credentials for fresh access tokens before the old ones expire.
+pub fn start_refresh(ss: Arc<SharedState>, wiki: String, cred: Credential, expires_in: Duration) {
+    tokio::spawn(async move {
+        let mut expires_in = expires_in;
+        loop {
+            tokio::time::sleep(expires_in.mul_f32(0.9)).await;
+            match login(&ss.config(), &wiki, &cred).await {
+                Ok((client, _, new_expiry)) => {
+                    *ss.clients[&wiki].lock().unwrap() = client;
+                    expires_in = new_expiry.unwrap_or(expires_in);
+                }
+                Err(e) => {
+                    error!(?e, %wiki, "couldn't refresh access token");
+                    expires_in = Duration::from_secs(60);
+                }
+            }
+        }
+    });
+}
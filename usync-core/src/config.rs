@@ -0,0 +1,666 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::{fs, io};
+
+use color_eyre::eyre::{Result, WrapErr, bail};
+use serde::Deserialize;
+use serde::de::{self, DeserializeOwned, Deserializer};
+use toml::{Table, Value};
+use tracing::{Level, warn};
+
+/// Page whose transclusions mark sync pages.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(untagged)]
+pub enum Anchor {
+    PageId(u64),
+    Title(String),
+}
+
+/// What to do with a page whose file was removed from the repository. Its header is taken off
+/// either way, which drops it from the map.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OnRemoved {
+    /// keep the content and ask the page's maintainers on its talk page to clean it up
+    #[default]
+    Notify,
+    /// put a notice on the page where the header was
+    Banner,
+    /// replace the page with the notice
+    Blank,
+}
+
+/// A page on another wiki that loads a globally deployed script.
+#[derive(Deserialize, Clone, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Loader {
+    /// synced page on this wiki
+    pub source: String,
+    /// the wiki the loader page lives on
+    pub wiki: String,
+    pub title: String,
+    /// make a null edit instead of purging
+    #[serde(default)]
+    pub touch: bool,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Wiki {
+    /// action API endpoint
+    pub api: String,
+    pub anchor: Anchor,
+    /// if set, sync pages are discovered as members of this tracking category
+    /// instead of through transclusions of `anchor`
+    pub category: Option<String>,
+    /// prepended to every edit summary, e.g. a link to the bot approval
+    pub summary_prefix: String,
+    /// language code edit summaries are worded in, English if we have no messages for it
+    pub language: String,
+    /// refreshed after their source is deployed, for global scripts
+    pub loaders: Vec<Loader>,
+    /// bot-maintained page every deployment is logged to
+    pub log_page: Option<String>,
+    /// bot-maintained page showing that we're alive, e.g. `User:DeadbeefBot II/status`
+    pub status_page: Option<String>,
+    /// user rights checked for at startup
+    pub required_rights: Vec<String>,
+    /// if set, only mappings listed on this page as `* repo → title` are honored. It should be
+    /// protected
+    pub approval_page: Option<String>,
+    /// JSON page mapping GitHub logins to wiki usernames, to credit authors in edit summaries
+    pub usernames_page: Option<String>,
+    /// page deployed gadgets should be registered on, e.g. `MediaWiki:Gadgets-definition`
+    pub gadgets_definition: Option<String>,
+    /// mark a deployed gadget's line on the definition page with the commit, so the edit
+    /// refreshes the gadget's cache
+    pub gadget_cache_bust: bool,
+    /// pages to purge after a synced module or TemplateStyles page is deployed, by its title
+    pub dependents: BTreeMap<String, Vec<String>>,
+    /// what to do with pages whose file is removed from the repository
+    pub on_removed: OnRemoved,
+}
+
+impl Default for Wiki {
+    fn default() -> Self {
+        Wiki {
+            api: "https://en.wikipedia.org/w/api.php".to_owned(),
+            // Wikipedia:USync
+            anchor: Anchor::PageId(80169683),
+            category: None,
+            summary_prefix: "[[[Wikipedia:Bots/Requests for approval/DeadbeefBot II|BOT]]]"
+                .to_owned(),
+            language: "en".to_owned(),
+            loaders: Vec::new(),
+            log_page: None,
+            status_page: None,
+            required_rights: vec!["bot".to_owned(), "edituserjs".to_owned()],
+            approval_page: None,
+            usernames_page: None,
+            gadgets_definition: None,
+            gadget_cache_bust: false,
+            dependents: BTreeMap::new(),
+            on_removed: OnRemoved::Notify,
+        }
+    }
+}
+
+impl Wiki {
+    /// Host name of the wiki, as used by `server_name` in EventStreams.
+    pub fn server_name(&self) -> Option<&str> {
+        let rest = self.api.split_once("://")?.1;
+        rest.split('/').next()
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Discovery {
+    /// rebuild the whole map this often, to catch anything the recent changes stream missed
+    pub rebuild_interval_secs: u64,
+    /// how long to wait for more reparse requests before rebuilding the map
+    pub debounce_secs: u64,
+    /// don't rebuild the map more often than this
+    pub min_interval_secs: u64,
+    /// content models of pages that may be synced
+    pub content_models: Vec<String>,
+    /// most titles a single repository and ref may register
+    pub max_titles_per_source: usize,
+    /// most titles a single repository may register, across all refs
+    pub max_titles_per_repo: usize,
+    /// if not empty, only these owners or `owner/repo`s may drive edits
+    pub allowed_repos: Vec<String>,
+    /// owners or `owner/repo`s that may never drive edits
+    pub denied_repos: Vec<String>,
+}
+
+impl Default for Discovery {
+    fn default() -> Self {
+        Discovery {
+            rebuild_interval_secs: 60 * 60,
+            debounce_secs: 2,
+            min_interval_secs: 30,
+            content_models: vec!["javascript".to_owned(), "css".to_owned()],
+            max_titles_per_source: 25,
+            max_titles_per_repo: 50,
+            allowed_repos: Vec::new(),
+            denied_repos: Vec::new(),
+        }
+    }
+}
+
+impl Discovery {
+    /// Whether the allow and deny lists let a repository drive edits.
+    pub fn allows_repo(&self, repo_url: &str) -> bool {
+        let name = repo_url
+            .strip_prefix("https://github.com/")
+            .unwrap_or(repo_url);
+        let owner = name.split('/').next().unwrap_or(name);
+        let listed = |e: &String| e.eq_ignore_ascii_case(name) || e.eq_ignore_ascii_case(owner);
+        (self.allowed_repos.is_empty() || self.allowed_repos.iter().any(listed))
+            && !self.denied_repos.iter().any(listed)
+    }
+
+    pub fn accepts(&self, contentmodel: &str) -> bool {
+        self.content_models.iter().any(|m| m == contentmodel)
+    }
+
+    /// Whether a page with this title could be in one of the accepted content models.
+    pub fn may_accept_title(&self, title: &str) -> bool {
+        self.content_models.iter().any(|m| match &**m {
+            "javascript" => title.ends_with(".js"),
+            "css" | "sanitized-css" => title.ends_with(".css"),
+            "json" => title.ends_with(".json"),
+            "Scribunto" => title.starts_with("Module:"),
+            _ => true,
+        })
+    }
+}
+
+/// Periodically commit the deployed wiki content back to a branch, for auditing.
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Mirror {
+    pub enabled: bool,
+    pub branch: String,
+    pub interval_secs: u64,
+}
+
+impl Default for Mirror {
+    fn default() -> Self {
+        Mirror {
+            enabled: false,
+            branch: "wiki-mirror".to_owned(),
+            interval_secs: 24 * 60 * 60,
+        }
+    }
+}
+
+/// Only deploy files under a license the wikis can take, e.g. one compatible with CC BY-SA.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Licenses {
+    /// SPDX identifiers a file has to be under, e.g. `MIT`. Empty lets anything through
+    pub allowed: Vec<String>,
+    /// for files that don't say, go by the repository's license as GitHub detects it
+    pub from_repo: bool,
+}
+
+impl Licenses {
+    pub fn enabled(&self) -> bool {
+        !self.allowed.is_empty()
+    }
+
+    /// Whether an SPDX expression like `MIT OR Apache-2.0` allows deploying the file.
+    pub fn allows(&self, expression: &str) -> bool {
+        let allowed = |id: &str| {
+            let id = id.trim().trim_matches(|c| c == '(' || c == ')');
+            self.allowed.iter().any(|a| a.eq_ignore_ascii_case(id))
+        };
+        // one of the alternatives, with all of its parts
+        expression
+            .split(" OR ")
+            .any(|alternative| alternative.split(" AND ").all(allowed))
+    }
+}
+
+/// Make every edit on a test wiki or on sandbox subpages instead of the synced pages, for
+/// trying out changes and bot trials without touching what people use.
+#[derive(Deserialize, Debug, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Staging {
+    pub enabled: bool,
+    /// edit on this wiki, by name, instead of the one the page is on
+    pub wiki: Option<String>,
+    /// put in front of titles, e.g. `User:DeadbeefBot II/sandbox/`
+    pub prefix: String,
+}
+
+impl Staging {
+    /// Where an edit to a page goes instead, if we're staging.
+    pub fn redirect(&self, wiki: &str, title: &str) -> Option<(String, String)> {
+        if !self.enabled {
+            return None;
+        }
+        let wiki = self.wiki.as_deref().unwrap_or(wiki);
+        Some((wiki.to_owned(), format!("{}{title}", self.prefix)))
+    }
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// address the webhook and status server listens on
+    pub bind: String,
+    /// refuse to start when the startup self-check finds problems, rather than warn about them
+    pub strict_startup: bool,
+    /// where persistent state such as the sync map snapshot is kept
+    pub state_dir: PathBuf,
+    /// sent with every request. Wiki operators expect contact information here
+    pub user_agent: String,
+    /// open pull requests for manual edits made on-wiki to synced pages
+    pub reverse_sync: bool,
+    /// report deploy results as commit statuses, when we have a GitHub token
+    pub commit_statuses: bool,
+    /// record each sync as a deployment through the GitHub deployments API
+    pub deployments: bool,
+    /// hold syncs that would shrink a page by more than this percentage, or leave little but
+    /// the header, until confirmed through the admin API. 100 never holds
+    pub max_shrink_percent: u64,
+    /// refuse TemplateStyles files using what the wiki would sanitize away, rather than
+    /// leaving it to the wiki
+    pub strict_template_styles: bool,
+    /// warn once fewer GitHub API requests than this are left in the rate limit window, so a
+    /// token can be added before syncs start failing. 0 never warns
+    pub github_ratelimit_warn_below: i64,
+    /// wait this long for more pushes to a page before syncing it, so a burst of pushes
+    /// makes a single edit. 0 syncs right away
+    pub coalesce_secs: u64,
+    /// give up on a single attempt at syncing a page after this long
+    pub sync_timeout_secs: u64,
+    /// most pages synced at once, across all pushes. The rest wait their turn
+    pub max_concurrent_syncs: usize,
+    /// most of those slots background work like reconciliation may take. It only gets them
+    /// while no webhook or admin sync is waiting
+    pub max_background_syncs: usize,
+    /// pushes waiting to be sorted before webhooks get turned away
+    pub push_queue_size: usize,
+    /// reparse requests waiting for the parser
+    pub reparse_queue_size: usize,
+    /// how long headers naming a renamed or transferred repository keep working
+    pub rename_grace_days: u64,
+    /// how often queued deployments are written to the wikis' log pages
+    pub deploy_log_interval_secs: u64,
+    /// how often the wikis' status pages are updated
+    pub heartbeat_interval_secs: u64,
+    /// besides on startup, check every mapped page against its branch this often. 0 only
+    /// checks on startup
+    pub reconcile_interval_secs: u64,
+    /// only report pages that drifted from their branch instead of fixing them
+    pub reconcile_report_only: bool,
+    /// lowest level of events sent to the Discord webhook, if one is configured
+    #[serde(deserialize_with = "deserialize_level")]
+    pub discord_level: Level,
+    /// every wiki we discover sync pages on and deploy to, by name
+    pub wikis: BTreeMap<String, Wiki>,
+    pub discovery: Discovery,
+    pub mirror: Mirror,
+    pub staging: Staging,
+    pub licenses: Licenses,
+}
+
+impl Config {
+    /// Carry over settings that only take effect at startup from the running config,
+    /// so a reload can't half-apply them.
+    pub fn keep_structure(&mut self, old: &Config) -> Result<()> {
+        let apis = |c: &Config| {
+            c.wikis
+                .iter()
+                .map(|(name, w)| (name.clone(), w.api.clone()))
+                .collect::<Vec<_>>()
+        };
+        if apis(self) != apis(old) {
+            bail!("wikis can't be added, removed or moved without a restart");
+        }
+
+        macro_rules! keep {
+            ($($field:ident).+) => {
+                if self.$($field).+ != old.$($field).+ {
+                    warn!(
+                        key = stringify!($($field).+),
+                        "changing this needs a restart, keeping the old value"
+                    );
+                    self.$($field).+ = old.$($field).+.clone();
+                }
+            };
+        }
+        keep!(bind);
+        keep!(state_dir);
+        keep!(user_agent);
+        keep!(push_queue_size);
+        keep!(reparse_queue_size);
+        keep!(max_concurrent_syncs);
+        keep!(max_background_syncs);
+        keep!(discord_level);
+        keep!(mirror.enabled);
+        // the interval itself may change, but not whether there is one
+        if (self.reconcile_interval_secs == 0) != (old.reconcile_interval_secs == 0) {
+            warn!(
+                key = "reconcile_interval_secs",
+                "changing this needs a restart, keeping the old value"
+            );
+            self.reconcile_interval_secs = old.reconcile_interval_secs;
+        }
+        Ok(())
+    }
+
+    /// Catch mistakes serde can't, before anything is started with them. Returns what's worth a
+    /// warning but still works, for the caller to log once it can.
+    pub fn validate(&self) -> Result<Vec<String>> {
+        let mut warnings = Vec::new();
+        if self.wikis.is_empty() {
+            bail!("no wikis configured");
+        }
+        for (name, wiki) in &self.wikis {
+            if !wiki.api.starts_with("https://") && !wiki.api.starts_with("http://") {
+                bail!(
+                    "wikis.{name}.api must be an http(s) URL, got `{}`",
+                    wiki.api
+                );
+            }
+            if !wiki.api.ends_with("/api.php") {
+                bail!("wikis.{name}.api must point to api.php, got `{}`", wiki.api);
+            }
+            if !crate::messages::known(&wiki.language) {
+                warnings.push(format!(
+                    "wikis.{name}.language: no messages for `{}`, using English",
+                    wiki.language
+                ));
+            }
+            for loader in &wiki.loaders {
+                if !self.wikis.contains_key(&loader.wiki) {
+                    bail!(
+                        "wikis.{name}.loaders: loader `{}` is on unknown wiki `{}`",
+                        loader.title,
+                        loader.wiki
+                    );
+                }
+            }
+        }
+
+        let nonzero = [
+            (
+                "discovery.rebuild_interval_secs",
+                self.discovery.rebuild_interval_secs,
+            ),
+            ("mirror.interval_secs", self.mirror.interval_secs),
+            ("deploy_log_interval_secs", self.deploy_log_interval_secs),
+            ("heartbeat_interval_secs", self.heartbeat_interval_secs),
+            ("sync_timeout_secs", self.sync_timeout_secs),
+            ("max_concurrent_syncs", self.max_concurrent_syncs as u64),
+            ("max_background_syncs", self.max_background_syncs as u64),
+            ("push_queue_size", self.push_queue_size as u64),
+            ("reparse_queue_size", self.reparse_queue_size as u64),
+        ];
+        for (key, value) in nonzero {
+            if value == 0 {
+                bail!("{key} must be greater than zero");
+            }
+        }
+        if self.max_shrink_percent > 100 {
+            bail!("max_shrink_percent can't be more than 100");
+        }
+        if self.staging.enabled {
+            match &self.staging.wiki {
+                Some(wiki) if !self.wikis.contains_key(wiki) => {
+                    bail!("staging.wiki: unknown wiki `{wiki}`");
+                }
+                None if self.staging.prefix.is_empty() => {
+                    bail!("staging needs a wiki or a prefix, or it would edit the real pages");
+                }
+                _ => {}
+            }
+        }
+        if self.discovery.content_models.is_empty() {
+            bail!("discovery.content_models is empty, nothing could be synced");
+        }
+        Ok(warnings)
+    }
+
+    /// Check a header's `wiki=` selectors against the wiki the page lives on.
+    pub fn check_wiki(&self, selectors: &[String], wiki: &str) -> Result<(), String> {
+        if selectors.is_empty() {
+            return Ok(());
+        }
+
+        let mut matched = false;
+        for selector in selectors {
+            let Some((name, _)) = self
+                .wikis
+                .iter()
+                .find(|(name, w)| *name == selector || w.server_name() == Some(selector))
+            else {
+                return Err(format!("unknown wiki `{selector}` in header"));
+            };
+            matched |= name == wiki;
+        }
+
+        if matched {
+            Ok(())
+        } else {
+            Err("header does not target this wiki".to_owned())
+        }
+    }
+}
+
+/// Read config.toml, adapted to Toolforge when we're running there.
+pub fn read() -> Result<Config> {
+    let mut config: Config = load(crate::toolforge::path("config.toml"), ENV_PREFIX)?;
+    crate::toolforge::adapt(&mut config);
+    Ok(config)
+}
+
+/// Environment variables starting with this override config keys.
+pub const ENV_PREFIX: &str = "USYNC_";
+/// Environment variables starting with this override secrets.
+pub const SECRET_ENV_PREFIX: &str = "USYNC_SECRET_";
+
+/// Read a TOML file, with keys overridden by environment variables starting with `prefix`.
+/// A missing file is treated as empty.
+pub fn load<T: DeserializeOwned>(path: impl AsRef<Path>, prefix: &str) -> Result<T> {
+    let path = path.as_ref();
+    let mut table = match fs::read_to_string(path) {
+        Ok(s) => s
+            .parse::<Table>()
+            .wrap_err_with(|| format!("invalid {}", path.display()))?,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Table::new(),
+        Err(e) => return Err(e.into()),
+    };
+    // secrets have their own, longer prefix
+    let vars = std::env::vars()
+        .filter(|(k, _)| prefix == SECRET_ENV_PREFIX || !k.starts_with(SECRET_ENV_PREFIX));
+    apply_env(&mut table, prefix, vars)?;
+    T::deserialize(table).wrap_err_with(|| format!("invalid {}", path.display()))
+}
+
+/// Set keys from `PREFIX_KEY=value` pairs, with `__` separating nested tables,
+/// e.g. `USYNC_DISCOVERY__DEBOUNCE_SECS=5`. Values are read as TOML if they parse as
+/// such, and as plain strings otherwise.
+fn apply_env(
+    table: &mut Table,
+    prefix: &str,
+    vars: impl IntoIterator<Item = (String, String)>,
+) -> Result<()> {
+    for (name, raw) in vars {
+        let Some(key) = name.strip_prefix(prefix) else {
+            continue;
+        };
+        let key = key.to_lowercase();
+        let mut path: Vec<&str> = key.split("__").collect();
+        let last = path.pop().unwrap();
+        let mut table = &mut *table;
+        for part in path {
+            let entry = table
+                .entry(part)
+                .or_insert_with(|| Value::Table(Table::new()));
+            let Value::Table(inner) = entry else {
+                bail!("{name}: `{part}` is not a table");
+            };
+            table = inner;
+        }
+        let value = format!("v = {raw}")
+            .parse::<Table>()
+            .ok()
+            .and_then(|mut t| t.remove("v"))
+            .unwrap_or(Value::String(raw));
+        table.insert(last.to_owned(), value);
+    }
+    Ok(())
+}
+
+fn deserialize_level<'de, D: Deserializer<'de>>(d: D) -> Result<Level, D::Error> {
+    String::deserialize(d)?.parse().map_err(de::Error::custom)
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            bind: "0.0.0.0:8000".to_owned(),
+            strict_startup: true,
+            state_dir: PathBuf::from("."),
+            user_agent: "fee1-dead/usync".to_owned(),
+            reverse_sync: false,
+            commit_statuses: true,
+            deployments: false,
+            strict_template_styles: true,
+            github_ratelimit_warn_below: 100,
+            max_shrink_percent: 80,
+            coalesce_secs: 5,
+            sync_timeout_secs: 10,
+            max_concurrent_syncs: 8,
+            max_background_syncs: 2,
+            push_queue_size: 10,
+            reparse_queue_size: 10,
+            rename_grace_days: 30,
+            deploy_log_interval_secs: 60 * 60,
+            heartbeat_interval_secs: 24 * 60 * 60,
+            reconcile_interval_secs: 24 * 60 * 60,
+            reconcile_report_only: false,
+            discord_level: Level::WARN,
+            wikis: BTreeMap::from([("enwiki".to_owned(), Wiki::default())]),
+            discovery: Discovery::default(),
+            mirror: Mirror::default(),
+            staging: Staging::default(),
+            licenses: Licenses::default(),
+        }
+    }
+}
+
+#[test]
+fn test_validate() {
+    assert_eq!(Config::default().validate().unwrap(), Vec::<String>::new());
+
+    let config: Config = toml::from_str("sync_timeout_secs = 0").unwrap();
+    assert!(config.validate().is_err());
+
+    let config: Config =
+        toml::from_str("[wikis.enwiki]\napi = \"https://en.wikipedia.org/w/index.php\"").unwrap();
+    assert!(config.validate().is_err());
+
+    assert!(toml::from_str::<Config>("reverse_synk = true").is_err());
+
+    let config: Config = toml::from_str("[wikis.enwiki]\nlanguage = \"xx\"").unwrap();
+    assert_eq!(config.validate().unwrap().len(), 1);
+}
+
+#[test]
+fn test_apply_env() {
+    let mut table: Table = "reverse_sync = false\n[wikis.enwiki]\nsummary_prefix = \"x\"\n"
+        .parse()
+        .unwrap();
+    let vars = [
+        ("USYNC_REVERSE_SYNC", "true"),
+        ("USYNC_BIND", "127.0.0.1:9000"),
+        ("USYNC_DISCOVERY__DEBOUNCE_SECS", "5"),
+        (
+            "USYNC_WIKIS__ENWIKI__API",
+            "https://en.wikipedia.org/w/api.php",
+        ),
+        ("HOME", "/root"),
+    ];
+    let vars = vars.map(|(k, v)| (k.to_owned(), v.to_owned()));
+    apply_env(&mut table, ENV_PREFIX, vars).unwrap();
+
+    let config = Config::deserialize(table).unwrap();
+    assert!(config.reverse_sync);
+    assert_eq!(config.bind, "127.0.0.1:9000");
+    assert_eq!(config.discovery.debounce_secs, 5);
+    assert_eq!(config.wikis["enwiki"].summary_prefix, "x");
+    assert_eq!(
+        config.wikis["enwiki"].api,
+        "https://en.wikipedia.org/w/api.php"
+    );
+}
+
+#[test]
+fn test_keep_structure() {
+    let old = Config::default();
+    let mut new: Config = toml::from_str("bind = \"127.0.0.1:1\"\nsync_timeout_secs = 30").unwrap();
+    new.keep_structure(&old).unwrap();
+    assert_eq!(new.bind, old.bind);
+    assert_eq!(new.sync_timeout_secs, 30);
+
+    let mut new: Config = toml::from_str("[wikis.metawiki]").unwrap();
+    assert!(new.keep_structure(&old).is_err());
+}
+
+#[test]
+fn test_staging() {
+    let mut staging = Staging {
+        enabled: false,
+        wiki: Some("testwiki".to_owned()),
+        prefix: "User:DeadbeefBot II/".to_owned(),
+    };
+    assert_eq!(staging.redirect("enwiki", "MediaWiki:Foo.js"), None);
+
+    staging.enabled = true;
+    assert_eq!(
+        staging.redirect("enwiki", "MediaWiki:Foo.js"),
+        Some((
+            "testwiki".to_owned(),
+            "User:DeadbeefBot II/MediaWiki:Foo.js".to_owned()
+        ))
+    );
+
+    let config: Config = toml::from_str("[staging]\nenabled = true").unwrap();
+    assert!(config.validate().is_err());
+    let config: Config = toml::from_str("[staging]\nenabled = true\nwiki = \"testwiki\"").unwrap();
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn test_licenses() {
+    let licenses = Licenses {
+        allowed: vec!["MIT".to_owned(), "CC-BY-SA-4.0".to_owned()],
+        from_repo: false,
+    };
+    assert!(licenses.allows("MIT"));
+    assert!(licenses.allows("mit"));
+    assert!(licenses.allows("GPL-3.0-only OR MIT"));
+    assert!(licenses.allows("(MIT AND CC-BY-SA-4.0)"));
+    assert!(!licenses.allows("MIT AND GPL-3.0-only"));
+    assert!(!licenses.allows("NOASSERTION"));
+}
+
+#[test]
+fn test_allows_repo() {
+    let mut discovery = Discovery::default();
+    assert!(discovery.allows_repo("https://github.com/fee1-dead/usync"));
+
+    discovery.allowed_repos = vec!["fee1-dead".to_owned(), "wikimedia/gadgets".to_owned()];
+    discovery.denied_repos = vec!["Fee1-Dead/secret".to_owned()];
+    assert!(discovery.allows_repo("https://github.com/fee1-dead/usync"));
+    assert!(discovery.allows_repo("https://github.com/wikimedia/gadgets"));
+    assert!(!discovery.allows_repo("https://github.com/wikimedia/other"));
+    assert!(!discovery.allows_repo("https://github.com/fee1-dead/secret"));
+}
@@ -0,0 +1,67 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre::Result;
+use tracing::{info, warn};
+
+use crate::{SharedState, set_period};
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+}
+
+/// Queue a line for the wiki's deployment log page, if it has one.
+pub fn log_deploy(ss: &SharedState, wiki: &str, title: &str, commit_url: &str, summary: &str) {
+    if ss.config().wikis[wiki].log_page.is_none() {
+        return;
+    }
+    let date = httpdate::fmt_http_date(SystemTime::now());
+    // commit messages can't close the nowiki early
+    let summary = summary.replace('<', "&lt;");
+    let line =
+        format!("* {date}: [[:{title}]] from [{commit_url} commit]: <nowiki>{summary}</nowiki>");
+    ss.deploy_log
+        .lock()
+        .unwrap()
+        .entry(wiki.to_owned())
+        .or_default()
+        .push(line);
+}
+
+/// Append the queued lines to a log page in a single edit.
+async fn flush(ss: &SharedState, wiki: &str, page: &str, lines: &[String]) -> Result<()> {
+    let text = format!("\n{}", lines.join("\n"));
+    let summary = format!("Logging {} deployments", lines.len());
+    crate::wp::append(&ss.client(wiki), page, &text, &summary).await
+}
+
+pub async fn task(cx: Context) {
+    let mut int =
+        tokio::time::interval(Duration::from_secs(cx.ss.config().deploy_log_interval_secs));
+
+    loop {
+        int.tick().await;
+        set_period(&mut int, cx.ss.config().deploy_log_interval_secs);
+
+        let pending = std::mem::take(&mut *cx.ss.deploy_log.lock().unwrap());
+        for (wiki, lines) in pending {
+            let Some(page) = cx.ss.config().wikis[&wiki].log_page.clone() else {
+                continue;
+            };
+            match flush(&cx.ss, &wiki, &page, &lines).await {
+                Ok(()) => info!(%wiki, count = lines.len(), "logged deployments"),
+                Err(e) => {
+                    warn!(?e, %wiki, "couldn't update deployment log, trying again later");
+                    let mut log = cx.ss.deploy_log.lock().unwrap();
+                    let queued = log.entry(wiki).or_default();
+                    // keep the log in order
+                    queued.splice(0..0, lines);
+                }
+            }
+        }
+    }
+}
+
+pub fn start(cx: Context) {
+    tokio::spawn(task(cx));
+}
@@ -0,0 +1,134 @@
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use serde_json::json;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::Layer;
+use tracing_subscriber::layer::Context;
+
+/// events are collected for this long and sent as one message
+const BATCH_WINDOW: Duration = Duration::from_secs(5);
+/// Discord's limit on message length
+const MAX_MESSAGE: usize = 2000;
+/// events beyond this many queued are dropped, so an error storm can't pile up
+const QUEUE: usize = 256;
+
+/// Forwards events at or above `level` to a Discord webhook.
+pub struct DiscordLayer {
+    level: Level,
+    send: Sender<String>,
+}
+
+#[derive(Default)]
+struct Fields {
+    message: String,
+    rest: String,
+}
+
+impl Visit for Fields {
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            let _ = write!(self.message, "{value:?}");
+        } else {
+            let _ = write!(self.rest, " {}={value:?}", field.name());
+        }
+    }
+}
+
+impl<S: Subscriber> Layer<S> for DiscordLayer {
+    fn on_event(&self, event: &Event<'_>, _: Context<'_, S>) {
+        let meta = event.metadata();
+        // don't report our own delivery failures back to ourselves
+        if *meta.level() > self.level || meta.target() == module_path!() {
+            return;
+        }
+        let mut fields = Fields::default();
+        event.record(&mut fields);
+        let line = format!(
+            "**{}** `{}` {}{}",
+            meta.level(),
+            meta.target(),
+            fields.message,
+            fields.rest
+        );
+        let _ = self.send.try_send(line);
+    }
+}
+
+/// Join queued lines into messages within Discord's length limit.
+fn batch(lines: Vec<String>) -> Vec<String> {
+    let mut messages = Vec::new();
+    let mut current = String::new();
+    for mut line in lines {
+        if line.len() > MAX_MESSAGE {
+            let mut end = MAX_MESSAGE - 1;
+            while !line.is_char_boundary(end) {
+                end -= 1;
+            }
+            line.truncate(end);
+            line.push('…');
+        }
+        if !current.is_empty() && current.len() + 1 + line.len() > MAX_MESSAGE {
+            messages.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(&line);
+    }
+    if !current.is_empty() {
+        messages.push(current);
+    }
+    messages
+}
+
+#[test]
+fn test_batch() {
+    assert_eq!(batch(vec!["a".into(), "b".into()]), ["a\nb"]);
+    assert!(batch(Vec::new()).is_empty());
+
+    let long = "x".repeat(1500);
+    let messages = batch(vec![long.clone(), long.clone(), "y".repeat(3000)]);
+    assert_eq!(messages.len(), 3);
+    assert!(messages.iter().all(|m| m.chars().count() <= MAX_MESSAGE));
+}
+
+async fn deliver(url: String, mut recv: Receiver<String>) {
+    let client = reqwest::Client::new();
+    while let Some(first) = recv.recv().await {
+        tokio::time::sleep(BATCH_WINDOW).await;
+        let mut lines = vec![first];
+        while let Ok(line) = recv.try_recv() {
+            lines.push(line);
+        }
+
+        for content in batch(lines) {
+            let res = client
+                .post(&url)
+                .json(&json!({ "content": content }))
+                .send()
+                .await;
+            match res {
+                Ok(res) if res.status() == reqwest::StatusCode::TOO_MANY_REQUESTS => {
+                    // drop this batch rather than fall further behind
+                    tracing::warn!("rate limited by discord");
+                    tokio::time::sleep(BATCH_WINDOW).await;
+                    break;
+                }
+                Ok(res) if !res.status().is_success() => {
+                    tracing::warn!(status = %res.status(), "couldn't post to discord");
+                }
+                Ok(_) => {}
+                Err(e) => tracing::warn!(?e, "couldn't post to discord"),
+            }
+        }
+    }
+}
+
+/// The layer, and the task delivering its events, which must be spawned on the runtime.
+pub fn layer(url: String, level: Level) -> (DiscordLayer, impl Future<Output = ()>) {
+    let (send, recv) = mpsc::channel(QUEUE);
+    (DiscordLayer { level, send }, deliver(url, recv))
+}
@@ -0,0 +1,62 @@
+//! Syncing a Markdown file to the documentation subpage of a synced page, as wikitext.
+
+use std::sync::Arc;
+
+use tracing::{error, instrument};
+
+use crate::github::{Fetched, get_file, repo_name};
+use crate::i18n::save_subpage;
+use crate::license;
+use crate::parser::Target;
+use crate::schedule::Tier;
+use crate::transform::markdown_to_wikitext;
+use crate::updater::edit_summary;
+use crate::{GitHubPush, SharedState};
+
+/// Where the documentation of a page goes.
+pub fn page(title: &str) -> String {
+    format!("{title}/doc")
+}
+
+/// Convert the page's Markdown documentation and save it to its documentation subpage.
+/// Returns whether that went through.
+#[instrument(skip_all, fields(sync_id = %push.sync_id, %wiki, title = %target.title))]
+pub async fn sync(ss: Arc<SharedState>, push: GitHubPush, wiki: String, target: Target) -> bool {
+    let Some(path) = &target.doc else {
+        return true;
+    };
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return true;
+    };
+
+    let url = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let markdown = match get_file(&ss, &url, &push.after, None).await {
+        Ok(Fetched::Content { text, .. }) => text,
+        Ok(Fetched::NotModified) => return true,
+        Err(e) => {
+            error!(?e, %path, "couldn't get documentation");
+            return false;
+        }
+    };
+    if license::check(&ss, repo, &push.after, &markdown)
+        .await
+        .is_err()
+    {
+        return false;
+    }
+
+    // relative links point next to the file, at the pushed commit
+    let dir = path.rsplit_once('/').map_or("", |(dir, _)| dir);
+    let base = match dir {
+        "" => format!("{}/blob/{}/", push.repository.html_url, push.after),
+        dir => format!("{}/blob/{}/{dir}/", push.repository.html_url, push.after),
+    };
+    let text = format!(
+        "<!-- generated from {path} in {}, edits here will be overwritten -->\n{}",
+        push.repository.html_url,
+        markdown_to_wikitext(&markdown, &base),
+    );
+    let summary = edit_summary(&ss, &wiki, &push);
+    let tier = Tier::of(&push);
+    save_subpage(&ss, &wiki, &page(&target.title), &text, &summary, tier).await
+}
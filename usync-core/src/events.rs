@@ -0,0 +1,94 @@
+use actix_web::web::Bytes;
+use actix_web::{HttpResponse, Responder, get, web};
+use futures_util::stream;
+use serde::Serialize;
+use tokio::sync::broadcast::error::RecvError;
+
+use crate::{SharedState, State};
+
+/// Something that happened to a push on its way to the wiki.
+#[derive(Serialize, Clone, Debug)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Activity {
+    WebhookReceived {
+        sync_id: String,
+        repo: String,
+        #[serde(rename = "ref")]
+        ref_: String,
+        sha: String,
+    },
+    SyncStarted {
+        sync_id: String,
+        wiki: String,
+        title: String,
+    },
+    EditSaved {
+        sync_id: String,
+        wiki: String,
+        title: String,
+        revid: Option<u64>,
+    },
+    SyncFailed {
+        sync_id: String,
+        wiki: String,
+        title: String,
+        reason: String,
+    },
+    /// reconciliation found a page that differs from its branch, and left it alone
+    DriftDetected {
+        sync_id: String,
+        wiki: String,
+        title: String,
+        sha: String,
+    },
+    /// the GitHub rate limit budget dropped below the configured threshold
+    RateLimitLow {
+        remaining: i64,
+        /// when the window resets, in seconds since the epoch
+        reset: i64,
+    },
+}
+
+impl Activity {
+    fn name(&self) -> &'static str {
+        match self {
+            Activity::WebhookReceived { .. } => "webhook_received",
+            Activity::SyncStarted { .. } => "sync_started",
+            Activity::EditSaved { .. } => "edit_saved",
+            Activity::SyncFailed { .. } => "sync_failed",
+            Activity::DriftDetected { .. } => "drift_detected",
+            Activity::RateLimitLow { .. } => "ratelimit_low",
+        }
+    }
+}
+
+/// Tell everyone listening on `/events`.
+pub fn publish(ss: &SharedState, activity: Activity) {
+    // nobody listening is fine
+    let _ = ss.events.send(activity);
+}
+
+/// Server-sent events for every [`Activity`].
+#[get("/events")]
+pub async fn events(state: web::Data<State>) -> impl Responder {
+    let recv = state.shared.events.subscribe();
+    let body = stream::unfold(recv, |mut recv| async move {
+        loop {
+            match recv.recv().await {
+                Ok(activity) => {
+                    let data = serde_json::to_string(&activity).unwrap_or_default();
+                    let message = format!("event: {}\ndata: {data}\n\n", activity.name());
+                    return Some((Ok::<_, actix_web::Error>(Bytes::from(message)), recv));
+                }
+                // a slow client misses some events rather than holding everyone up
+                Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    });
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(body)
+}
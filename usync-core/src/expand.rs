@@ -0,0 +1,207 @@
+//! Placeholders like `/* usync:commit */` filled in when a file is deployed, so scripts can
+//! tell which version of them is running.
+
+use std::time::SystemTime;
+
+const OPEN: &str = "/* usync:";
+const CLOSE: &str = " */";
+
+/// Values for the placeholders in a deployed file.
+pub struct Vars<'a> {
+    /// `/* usync:commit */`, the deployed commit
+    pub commit: &'a str,
+    /// `/* usync:ref */`, the ref it was pushed to
+    pub ref_: &'a str,
+    /// `/* usync:date */`, the day of the deployment
+    pub date: String,
+    /// `/* usync:version */`, the `version` in the repository's package.json
+    pub version: Option<String>,
+}
+
+impl Vars<'_> {
+    fn get(&self, name: &str) -> Option<&str> {
+        match name {
+            "commit" => Some(self.commit),
+            "ref" => Some(self.ref_),
+            "date" => Some(&self.date),
+            "version" => self.version.as_deref(),
+            _ => None,
+        }
+    }
+}
+
+enum Piece<'a> {
+    Text(&'a str),
+    Placeholder(&'a str),
+}
+
+/// Split a file into text and the names of known placeholders.
+fn pieces(text: &str) -> Vec<Piece<'_>> {
+    let mut pieces = Vec::new();
+    let mut rest = text;
+    while let Some(start) = rest.find(OPEN) {
+        let after = &rest[start + OPEN.len()..];
+        let name = after
+            .find(CLOSE)
+            .map(|end| &after[..end])
+            .filter(|name| matches!(*name, "commit" | "ref" | "date" | "version"));
+        let Some(name) = name else {
+            // not one of ours, keep it as it is
+            pieces.push(Piece::Text(&rest[..start + OPEN.len()]));
+            rest = after;
+            continue;
+        };
+        pieces.push(Piece::Text(&rest[..start]));
+        pieces.push(Piece::Placeholder(name));
+        rest = &after[name.len() + CLOSE.len()..];
+    }
+    pieces.push(Piece::Text(rest));
+    pieces
+}
+
+/// Whether the file uses a placeholder.
+pub fn uses(text: &str, name: &str) -> bool {
+    pieces(text)
+        .iter()
+        .any(|p| matches!(p, Piece::Placeholder(n) if *n == name))
+}
+
+/// Fill in the placeholders we have values for.
+pub fn expand(text: &str, vars: &Vars<'_>) -> String {
+    let mut out = String::with_capacity(text.len());
+    for piece in pieces(text) {
+        match piece {
+            Piece::Text(text) => out.push_str(text),
+            Piece::Placeholder(name) => match vars.get(name) {
+                Some(value) => out.push_str(value),
+                None => {
+                    out.push_str(OPEN);
+                    out.push_str(name);
+                    out.push_str(CLOSE);
+                }
+            },
+        }
+    }
+    out
+}
+
+/// Whether `deployed` is `text` expanded with any values at all, so the file itself hasn't
+/// changed even if the commit or the date has.
+pub fn same_template(text: &str, deployed: &str) -> bool {
+    let pieces = pieces(text);
+    let mut rest = deployed;
+    let mut pieces = pieces.iter().peekable();
+    while let Some(piece) = pieces.next() {
+        match piece {
+            Piece::Text(text) => match rest.strip_prefix(text) {
+                Some(after) => rest = after,
+                None => return false,
+            },
+            Piece::Placeholder(_) => {
+                // a value runs up to the next bit of text, and never across lines
+                let next = match pieces.peek() {
+                    Some(Piece::Text(next)) if !next.is_empty() => *next,
+                    _ => "\n",
+                };
+                let end = rest.find(next).unwrap_or(rest.len());
+                if rest[..end].contains('\n') {
+                    return false;
+                }
+                rest = &rest[end..];
+            }
+        }
+    }
+    rest.is_empty()
+}
+
+/// Put the placeholders in `text` back into `edited`, a copy of the deployed file that was
+/// changed by hand, so the change can go back to the repository. Every line with a placeholder
+/// has to still be there, in order, with only the values different. Returns `None` if one isn't.
+pub fn restore(text: &str, edited: &str) -> Option<String> {
+    // the wiki trims the last newline, so line endings are left as they are in `edited`
+    let mut lines = edited.split('\n').collect::<Vec<_>>();
+    let mut next = 0;
+    for line in text.split('\n') {
+        if !pieces(line)
+            .iter()
+            .any(|p| matches!(p, Piece::Placeholder(_)))
+        {
+            continue;
+        }
+        let found = lines[next..]
+            .iter()
+            .position(|edited| same_template(line, edited))?;
+        lines[next + found] = line;
+        next += found + 1;
+    }
+    Some(lines.join("\n"))
+}
+
+/// Today's date in UTC, as `YYYY-MM-DD`.
+pub fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(SystemTime::UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    // civil date from days since the epoch, after Howard Hinnant's `civil_from_days`
+    let z = (secs / 86400) as i64 + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{year:04}-{month:02}-{day:02}")
+}
+
+#[test]
+fn test_expand() {
+    let vars = Vars {
+        commit: "abc123",
+        ref_: "refs/heads/main",
+        date: "2025-01-02".to_owned(),
+        version: None,
+    };
+    let text = "var v = '/* usync:commit */'; // /* usync:date */ /* usync:version */ /* other */";
+    let expanded = expand(text, &vars);
+    assert_eq!(
+        expanded,
+        "var v = 'abc123'; // 2025-01-02 /* usync:version */ /* other */"
+    );
+    assert!(same_template(text, &expanded));
+    assert!(same_template(
+        text,
+        "var v = 'def456'; // 2025-03-04 1.2.0 /* other */"
+    ));
+    assert!(!same_template(
+        text,
+        "var v = 'def456'; // 2025-03-04 1.2.0 /* changed */"
+    ));
+    assert!(!same_template("a /* usync:commit */", "b abc"));
+    assert!(same_template("plain", "plain"));
+}
+
+#[test]
+fn test_restore() {
+    let text = "// v/* usync:version */\nvar a = 1;\nvar c = '/* usync:commit */';\n";
+    assert_eq!(
+        restore(
+            text,
+            "// v1.2.0\nvar a = 2;\nvar b = 3;\nvar c = 'abc123';\n"
+        )
+        .as_deref(),
+        Some("// v/* usync:version */\nvar a = 2;\nvar b = 3;\nvar c = '/* usync:commit */';\n")
+    );
+    // the line with the commit was edited, so there's nothing to put it back into
+    assert_eq!(
+        restore(text, "// v1.2.0\nvar a = 1;\nvar c = abc123;\n"),
+        None
+    );
+    assert_eq!(
+        restore("a /* usync:commit */\n", "a abc123").as_deref(),
+        Some("a /* usync:commit */")
+    );
+    assert_eq!(restore("plain\n", "edited\n").as_deref(), Some("edited\n"));
+}
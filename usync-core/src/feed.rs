@@ -0,0 +1,60 @@
+use std::fmt::Write;
+
+use actix_web::{HttpResponse, Responder, get, web};
+use tracing::error;
+
+use crate::State;
+use crate::ui::escape;
+use crate::updater::index_url;
+
+/// how many deployments the feed shows
+const ENTRIES: usize = 50;
+
+/// An Atom feed of recent deployments.
+#[get("/feed.atom")]
+pub async fn feed(state: web::Data<State>) -> impl Responder {
+    let ss = &state.shared;
+    let deploys = match ss.audit.recent_deploys(ENTRIES) {
+        Ok(deploys) => deploys,
+        Err(e) => {
+            error!(?e, "couldn't read the audit log");
+            return HttpResponse::InternalServerError().finish();
+        }
+    };
+
+    let updated = deploys
+        .first()
+        .map(|d| d.timestamp.as_str())
+        .unwrap_or("1970-01-01T00:00:00Z");
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"utf-8\"?>\
+        <feed xmlns=\"http://www.w3.org/2005/Atom\">\
+        <title>usync deployments</title><id>urn:usync:deployments</id>\
+        <updated>{updated}</updated><author><name>usync</name></author>"
+    );
+    for deploy in deploys {
+        // the wiki may have been removed from the config since
+        let link = match (deploy.revid, ss.config().wikis.contains_key(&deploy.wiki)) {
+            (Some(revid), true) => index_url(ss, &deploy.wiki, &[("diff", &revid.to_string())]),
+            _ => format!("{}/commit/{}", deploy.repo, deploy.sha),
+        };
+        let _ = write!(
+            xml,
+            "<entry><title>{} on {}</title><id>urn:usync:attempt:{}</id><updated>{}</updated>\
+            <link href=\"{}\"/><summary>{} ({}/commit/{})</summary></entry>",
+            escape(&deploy.title),
+            escape(&deploy.wiki),
+            deploy.id,
+            deploy.timestamp,
+            escape(&link),
+            escape(deploy.summary.as_deref().unwrap_or_default()),
+            escape(&deploy.repo),
+            escape(&deploy.sha),
+        );
+    }
+    xml.push_str("</feed>");
+
+    HttpResponse::Ok()
+        .content_type("application/atom+xml; charset=utf-8")
+        .body(xml)
+}
@@ -0,0 +1,147 @@
+//! Gadgets deployed to `MediaWiki:Gadget-*` pages, and the definition page registering them.
+
+use color_eyre::eyre::{Result, bail};
+use tracing::{info, warn};
+
+use crate::SharedState;
+use crate::wp::{self, Missing};
+
+const PREFIX: &str = "MediaWiki:Gadget-";
+/// marks a definition line with the commit last deployed to it. The Gadgets extension ignores
+/// comments, but editing the page refreshes the gadgets' caches
+const MARKER: &str = "<!-- usync:";
+
+/// How a gadget page is listed on the definition page, e.g. `foo.js` for
+/// `MediaWiki:Gadget-foo.js`.
+pub fn page_name(title: &str) -> Option<&str> {
+    let name = title.strip_prefix(PREFIX)?;
+    [".js", ".css", ".json"]
+        .iter()
+        .any(|ext| name.ends_with(ext))
+        .then_some(name)
+}
+
+/// Which line of the definition registers a page, going by `* gadget[options]|page|page`.
+fn find(definition: &str, name: &str) -> Option<usize> {
+    definition.lines().position(|line| {
+        let Some(line) = line.strip_prefix('*') else {
+            return false;
+        };
+        let line = line.split("<!--").next().unwrap_or(line);
+        // options can have `|`s of their own
+        let pages = match line.split_once(']') {
+            Some((_, pages)) => pages,
+            None => line.split_once('|').map_or("", |(_, pages)| pages),
+        };
+        pages.split('|').any(|page| page.trim() == name)
+    })
+}
+
+/// Mark the line registering a page with a commit, replacing an earlier mark. `None` if the
+/// page isn't registered.
+fn mark(definition: &str, name: &str, sha: &str) -> Option<String> {
+    let index = find(definition, name)?;
+    let short = &sha[..sha.len().min(7)];
+    let mut text = definition
+        .lines()
+        .enumerate()
+        .map(|(i, line)| {
+            if i != index {
+                return line.to_owned();
+            }
+            let line = match line.find(MARKER) {
+                Some(at) => line[..at].trim_end(),
+                None => line,
+            };
+            format!("{line} {MARKER} {short} -->")
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+    if definition.ends_with('\n') {
+        text.push('\n');
+    }
+    Some(text)
+}
+
+/// How many times to start over when someone else edits the definition while we do.
+const CONFLICT_RETRIES: u32 = 3;
+
+async fn update(ss: &SharedState, wiki: &str, title: &str, sha: &str) -> Result<()> {
+    let config = &ss.config().wikis[wiki];
+    let (Some(page), Some(name)) = (&config.gadgets_definition, page_name(title)) else {
+        return Ok(());
+    };
+    let client = ss.client(wiki);
+    let mut retries = CONFLICT_RETRIES;
+    loop {
+        let revision = match wp::fetch(&client, page).await {
+            Ok(revision) => revision,
+            Err(e) if e.is::<Missing>() => bail!("{page} doesn't exist"),
+            Err(e) => return Err(e),
+        };
+        let definition = &revision.slots.main.content;
+        if find(definition, name).is_none() {
+            bail!("{title} isn't registered as a gadget on {page}");
+        }
+        if !config.gadget_cache_bust {
+            return Ok(());
+        }
+
+        let Some(text) = mark(definition, name, sha) else {
+            return Ok(());
+        };
+        let summary = format!("{} Refreshing the cache of {title}", config.summary_prefix);
+        // interface admins may be editing the definition too, don't overwrite their changes
+        let res = wp::save_over(&client, page, &text, &summary, &revision).await?;
+        match res.error {
+            None => break,
+            Some(e) if e.code == "editconflict" && retries > 0 => {
+                info!(%wiki, %page, "edit conflict on the gadget definition, trying again");
+                retries -= 1;
+            }
+            Some(e) => bail!("{}: {}", e.code, e.info),
+        }
+    }
+    info!(%wiki, %title, "marked gadget definition");
+    Ok(())
+}
+
+/// After a gadget page is deployed, check that it's registered, and mark its definition line
+/// to refresh its cache if configured to.
+pub async fn deployed(ss: &SharedState, wiki: &str, title: &str, sha: &str) {
+    if let Err(e) = update(ss, wiki, title, sha).await {
+        warn!(?e, %wiki, %title, "couldn't check the gadget definition");
+    }
+}
+
+#[test]
+fn test_page_name() {
+    assert_eq!(page_name("MediaWiki:Gadget-foo.js"), Some("foo.js"));
+    assert_eq!(page_name("MediaWiki:Gadget-foo.css"), Some("foo.css"));
+    assert_eq!(page_name("MediaWiki:Gadgets-definition"), None);
+    assert_eq!(page_name("User:Foo/common.js"), None);
+}
+
+#[test]
+fn test_mark() {
+    let definition = "== Tools ==\n\
+        * foo[ResourceLoader|default]|foo.js|foo.css\n\
+        * bar|bar.js <!-- usync: 1111111 -->\n";
+    assert_eq!(find(definition, "foo.css"), Some(1));
+    assert_eq!(find(definition, "foo"), None);
+    assert_eq!(find(definition, "default"), None);
+    assert_eq!(mark(definition, "baz.js", "abc"), None);
+    assert_eq!(
+        mark(definition, "bar.js", "2222222222").unwrap(),
+        "== Tools ==\n\
+        * foo[ResourceLoader|default]|foo.js|foo.css\n\
+        * bar|bar.js <!-- usync: 2222222 -->\n"
+    );
+    assert_eq!(
+        mark(definition, "foo.js", "3333333")
+            .unwrap()
+            .lines()
+            .nth(1),
+        Some("* foo[ResourceLoader|default]|foo.js|foo.css <!-- usync: 3333333 -->")
+    );
+}
@@ -0,0 +1,818 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use std::io::{Cursor, Read};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use color_eyre::eyre::{Result, bail};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Method, RequestBuilder, Response, StatusCode};
+use serde::Deserialize;
+use serde_json::json;
+use tracing::warn;
+use zip::ZipArchive;
+
+use crate::SharedState;
+use crate::events::{Activity, publish};
+use crate::transform;
+
+/// `owner/name` of a GitHub repository URL.
+pub fn repo_name(url: &str) -> Option<&str> {
+    let repo = url.strip_prefix("https://github.com/")?;
+    Some(repo.strip_suffix('/').unwrap_or(repo))
+}
+
+fn build(ss: &SharedState, method: Method, url: &str, accept: &str) -> RequestBuilder {
+    let req = ss.req.request(method, url).header("Accept", accept);
+    match &ss.github_token {
+        Some(token) => req.bearer_auth(token),
+        None => req,
+    }
+}
+
+/// Start a request to the GitHub REST API, authenticated if we have a token.
+pub fn request(ss: &SharedState, method: Method, url: &str) -> RequestBuilder {
+    build(ss, method, url, "application/vnd.github+json")
+}
+
+/// Fetch from the contents API, getting the file itself rather than JSON metadata.
+pub fn get_raw(ss: &SharedState, url: &str) -> RequestBuilder {
+    build(ss, Method::GET, url, "application/vnd.github.raw+json")
+}
+
+/// GitHub asked us to back off before making more requests.
+#[derive(Debug)]
+pub struct RateLimited {
+    pub retry_after: Duration,
+}
+
+impl fmt::Display for RateLimited {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "rate limited by github for {:?}", self.retry_after)
+    }
+}
+
+impl std::error::Error for RateLimited {}
+
+fn header<'a>(res: &'a Response, name: &str) -> Option<&'a str> {
+    res.headers().get(name)?.to_str().ok()
+}
+
+/// How long GitHub wants us to wait, if this is a primary or secondary rate limit response.
+fn rate_limit(res: &Response) -> Option<Duration> {
+    if !matches!(
+        res.status(),
+        StatusCode::FORBIDDEN | StatusCode::TOO_MANY_REQUESTS
+    ) {
+        return None;
+    }
+
+    if let Some(secs) = header(res, "retry-after").and_then(|s| s.parse().ok()) {
+        return Some(Duration::from_secs(secs));
+    }
+
+    if header(res, "x-ratelimit-remaining") == Some("0") {
+        let reset = header(res, "x-ratelimit-reset")?.parse::<u64>().ok()?;
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).ok()?.as_secs();
+        // give the reset a second of slack
+        return Some(Duration::from_secs(reset.saturating_sub(now) + 1));
+    }
+
+    None
+}
+
+/// Record the rate limit budget GitHub reports, warning when it first drops below the threshold.
+fn track_budget(ss: &SharedState, res: &Response) {
+    let metrics = &ss.metrics;
+    let number = |name| header(res, name).and_then(|s| s.parse::<i64>().ok());
+    if let Some(limit) = number("x-ratelimit-limit") {
+        metrics
+            .github_ratelimit_limit
+            .store(limit, Ordering::Relaxed);
+    }
+    let reset = number("x-ratelimit-reset");
+    if let Some(reset) = reset {
+        metrics
+            .github_ratelimit_reset
+            .store(reset, Ordering::Relaxed);
+    }
+    let Some(remaining) = number("x-ratelimit-remaining") else {
+        return;
+    };
+    let before = metrics
+        .github_ratelimit_remaining
+        .swap(remaining, Ordering::Relaxed);
+    let threshold = ss.config().github_ratelimit_warn_below;
+    if remaining < threshold && (before < 0 || before >= threshold) {
+        let reset = reset.unwrap_or(-1);
+        warn!(remaining, reset, "running low on GitHub API requests");
+        publish(ss, Activity::RateLimitLow { remaining, reset });
+    }
+}
+
+/// Send a request to GitHub, recording the remaining quota and turning rate limit responses
+/// into a [`RateLimited`] error.
+pub async fn send(ss: &SharedState, req: RequestBuilder) -> Result<Response> {
+    let res = req.send().await?;
+    track_budget(ss, &res);
+    if let Some(retry_after) = rate_limit(&res) {
+        return Err(RateLimited { retry_after }.into());
+    }
+    Ok(res)
+}
+
+pub enum Fetched {
+    /// the file still matches the ETag we sent
+    NotModified,
+    Content {
+        text: String,
+        etag: Option<String>,
+    },
+}
+
+/// Fetch a file from the contents API, conditionally on it having changed from `etag`.
+pub async fn get_file(
+    ss: &SharedState,
+    url: &str,
+    ref_: &str,
+    etag: Option<&str>,
+) -> Result<Fetched> {
+    let mut req = get_raw(ss, url).query(&[("ref", ref_)]);
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+
+    let res = send(ss, req).await?;
+    if res.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Fetched::NotModified);
+    }
+
+    let res = res.error_for_status()?;
+    let etag = res
+        .headers()
+        .get(ETAG)
+        .and_then(|v| v.to_str().ok())
+        .map(ToOwned::to_owned);
+    Ok(Fetched::Content {
+        text: transform::decode(res.bytes().await?.to_vec())?,
+        etag,
+    })
+}
+
+/// how many times a transient failure to fetch content is retried
+const FETCH_RETRIES: u32 = 3;
+/// wait before the first retry, doubled for each one after
+const FETCH_BACKOFF: Duration = Duration::from_millis(500);
+
+/// Whether a request failed in a way that may go away by itself: a timeout, a dropped
+/// connection or a 5xx.
+fn transient(e: &color_eyre::Report) -> bool {
+    e.downcast_ref::<reqwest::Error>().is_some_and(|e| {
+        e.is_timeout() || e.is_connect() || e.status().is_some_and(|s| s.is_server_error())
+    })
+}
+
+/// Somewhere between half of `d` and `d`, so retries from concurrent syncs spread out.
+fn jitter(d: Duration) -> Duration {
+    let random = RandomState::new().build_hasher().finish();
+    d / 2 + d.mul_f64((random % 1000) as f64 / 2000.0)
+}
+
+/// [`get_file`], retrying transient failures with exponential backoff. Every retry is
+/// counted in `retries`.
+pub async fn get_file_retrying(
+    ss: &SharedState,
+    url: &str,
+    ref_: &str,
+    etag: Option<&str>,
+    retries: &AtomicU32,
+) -> Result<Fetched> {
+    let mut backoff = FETCH_BACKOFF;
+    let mut attempt = 0;
+    loop {
+        match get_file(ss, url, ref_, etag).await {
+            Err(e) if attempt < FETCH_RETRIES && transient(&e) => {
+                attempt += 1;
+                retries.fetch_add(1, Ordering::Relaxed);
+                let delay = jitter(backoff);
+                warn!(?e, attempt, ?delay, "couldn't fetch from github, retrying");
+                tokio::time::sleep(delay).await;
+                backoff *= 2;
+            }
+            res => return res,
+        }
+    }
+}
+
+/// Fetch a file from raw.githubusercontent.com, which keeps working when the API is down or
+/// we've used up our quota.
+pub async fn get_raw_fallback(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    path: &str,
+) -> Result<String> {
+    let mut req = ss.req.get(format!(
+        "https://raw.githubusercontent.com/{repo}/{sha}/{path}"
+    ));
+    if let Some(token) = &ss.github_token {
+        req = req.bearer_auth(token);
+    }
+    let body = req.send().await?.error_for_status()?.bytes().await?;
+    Ok(transform::decode(body.to_vec())?)
+}
+
+/// GitHub rejects status descriptions longer than 140 characters.
+fn truncate_description(description: &str) -> &str {
+    match description.char_indices().nth(140) {
+        Some((i, _)) => &description[..i],
+        None => description,
+    }
+}
+
+/// Set a commit status on `sha`. `state` is one of `error`, `failure`, `pending` or `success`.
+pub async fn create_status(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    state: &str,
+    context: &str,
+    description: &str,
+    target_url: Option<&str>,
+) -> Result<()> {
+    let description = truncate_description(description);
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/statuses/{sha}"),
+    )
+    .json(&json!({
+        "state": state,
+        "context": context,
+        "description": description,
+        "target_url": target_url,
+    }));
+    send(ss, req).await?.error_for_status()?;
+    Ok(())
+}
+
+/// A finished deployment of a commit to some environment.
+pub struct Deployment<'a> {
+    pub environment: &'a str,
+    pub environment_url: &'a str,
+    /// `error`, `failure` or `success`
+    pub state: &'a str,
+    pub description: &'a str,
+    pub log_url: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct Created {
+    id: u64,
+}
+
+/// Record a deployment of `sha` along with how it went.
+pub async fn create_deployment(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    deployment: &Deployment<'_>,
+) -> Result<()> {
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/deployments"),
+    )
+    .json(&json!({
+        "ref": sha,
+        "environment": deployment.environment,
+        "description": truncate_description(deployment.description),
+        // the deployment already happened, don't hold it on merging or other checks
+        "auto_merge": false,
+        "required_contexts": [],
+        "production_environment": true,
+    }));
+    let Created { id } = send(ss, req).await?.error_for_status()?.json().await?;
+
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/deployments/{id}/statuses"),
+    )
+    .json(&json!({
+        "state": deployment.state,
+        "environment_url": deployment.environment_url,
+        "log_url": deployment.log_url,
+        "description": truncate_description(deployment.description),
+    }));
+    send(ss, req).await?.error_for_status()?;
+    Ok(())
+}
+
+/// Comment on a commit. Comments also show up on pull requests containing the commit.
+pub async fn create_commit_comment(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    body: &str,
+) -> Result<()> {
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/commits/{sha}/comments"),
+    )
+    .json(&json!({ "body": body }));
+    send(ss, req).await?.error_for_status()?;
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Artifacts {
+    artifacts: Vec<Artifact>,
+}
+
+#[derive(Deserialize)]
+struct Artifact {
+    name: String,
+    archive_download_url: String,
+    expired: bool,
+}
+
+/// Get a file out of a workflow run's artifact. Downloading artifacts always needs a token.
+pub async fn get_artifact_file(
+    ss: &SharedState,
+    artifacts_url: &str,
+    name: &str,
+    path: &str,
+) -> Result<String> {
+    if ss.github_token.is_none() {
+        bail!("downloading artifacts needs a GitHub token");
+    }
+
+    let req = request(ss, Method::GET, artifacts_url).query(&[("name", name)]);
+    let artifacts = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<Artifacts>()
+        .await?;
+    let Some(artifact) = artifacts
+        .artifacts
+        .into_iter()
+        .find(|a| a.name == name && !a.expired)
+    else {
+        bail!("no artifact named {name}");
+    };
+
+    let req = request(ss, Method::GET, &artifact.archive_download_url);
+    let archive = send(ss, req).await?.error_for_status()?.bytes().await?;
+    let mut archive = ZipArchive::new(Cursor::new(archive))?;
+    let mut file = archive.by_name(path)?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes)?;
+    Ok(transform::decode(bytes)?)
+}
+
+#[derive(Deserialize)]
+struct Comparison {
+    /// how head relates to base: `ahead`, `behind`, `identical` or `diverged`
+    status: String,
+    #[serde(default)]
+    files: Vec<ChangedFile>,
+}
+
+#[derive(Deserialize)]
+struct ChangedFile {
+    filename: String,
+    status: String,
+    /// where a renamed file used to be
+    #[serde(default)]
+    previous_filename: Option<String>,
+}
+
+/// How `head` relates to `base`: `ahead`, `behind`, `identical` or `diverged`.
+pub async fn compare_status(
+    ss: &SharedState,
+    repo: &str,
+    base: &str,
+    head: &str,
+) -> Result<String> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/compare/{base}...{head}"),
+    )
+    // we only want the status, not the commit list
+    .query(&[("per_page", "1")]);
+    let comparison = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<Comparison>()
+        .await?;
+    Ok(comparison.status)
+}
+
+#[derive(Deserialize)]
+pub struct Verification {
+    pub verified: bool,
+    /// why the signature is or isn't valid, e.g. `unsigned` or `valid`
+    pub reason: String,
+}
+
+#[derive(Deserialize)]
+struct CommitDetails {
+    verification: Verification,
+}
+
+#[derive(Deserialize)]
+struct CommitInfo {
+    commit: CommitDetails,
+}
+
+/// Whether GitHub could verify the signature of a commit.
+pub async fn commit_verification(ss: &SharedState, repo: &str, sha: &str) -> Result<Verification> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/commits/{sha}"),
+    );
+    let info = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<CommitInfo>()
+        .await?;
+    Ok(info.commit.verification)
+}
+
+#[derive(Deserialize)]
+struct Message {
+    message: String,
+}
+
+#[derive(Deserialize)]
+struct CommitMessage {
+    commit: Message,
+}
+
+/// The message of the latest commit up to `sha` that changed `path`, or `None` if none did.
+pub async fn last_change(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    path: &str,
+) -> Result<Option<String>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/commits"),
+    )
+    .query(&[("sha", sha), ("path", path), ("per_page", "1")]);
+    let commits = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<Vec<CommitMessage>>()
+        .await?;
+    Ok(commits.into_iter().next().map(|c| c.commit.message))
+}
+
+async fn changed_files(
+    ss: &SharedState,
+    repo: &str,
+    base: &str,
+    head: &str,
+) -> Result<Vec<ChangedFile>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/compare/{base}...{head}"),
+    );
+    let comparison = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<Comparison>()
+        .await?;
+    Ok(comparison.files)
+}
+
+/// Files added or changed between two commits.
+pub async fn compare_files(
+    ss: &SharedState,
+    repo: &str,
+    base: &str,
+    head: &str,
+) -> Result<Vec<String>> {
+    Ok(changed_files(ss, repo, base, head)
+        .await?
+        .into_iter()
+        .filter(|f| f.status != "removed")
+        .map(|f| f.filename)
+        .collect())
+}
+
+/// Where `path` was moved to between two commits, or `None` if it wasn't renamed.
+pub async fn renamed_to(
+    ss: &SharedState,
+    repo: &str,
+    base: &str,
+    head: &str,
+    path: &str,
+) -> Result<Option<String>> {
+    Ok(changed_files(ss, repo, base, head)
+        .await?
+        .into_iter()
+        .find(|f| f.status == "renamed" && f.previous_filename.as_deref() == Some(path))
+        .map(|f| f.filename))
+}
+
+/// A Git LFS pointer, committed in place of a file stored outside the repository.
+#[derive(Debug, PartialEq, Eq)]
+pub struct LfsPointer {
+    pub oid: String,
+    pub size: u64,
+}
+
+/// Parse `text` as a Git LFS pointer file, if it is one.
+pub fn parse_lfs_pointer(text: &str) -> Option<LfsPointer> {
+    let mut lines = text.lines();
+    if !lines
+        .next()?
+        .starts_with("version https://git-lfs.github.com/spec/")
+    {
+        return None;
+    }
+
+    let (mut oid, mut size) = (None, None);
+    for line in lines {
+        let (key, value) = line.split_once(' ')?;
+        match key {
+            "oid" => oid = Some(value.strip_prefix("sha256:")?.to_owned()),
+            "size" => size = Some(value.parse().ok()?),
+            _ => {}
+        }
+    }
+    Some(LfsPointer {
+        oid: oid?,
+        size: size?,
+    })
+}
+
+#[test]
+fn test_parse_lfs_pointer() {
+    let pointer = "version https://git-lfs.github.com/spec/v1
+oid sha256:4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393
+size 12345
+";
+    assert_eq!(
+        parse_lfs_pointer(pointer),
+        Some(LfsPointer {
+            oid: "4d7a214614ab2935c943f9e0ff69d22eadbb8f32b1258daaa5e2ca24d17e2393".into(),
+            size: 12345,
+        })
+    );
+    assert_eq!(
+        parse_lfs_pointer("// {{Wikipedia:USync |repo=...}}\n"),
+        None
+    );
+    assert_eq!(
+        parse_lfs_pointer("version https://git-lfs.github.com/spec/v1\nsize 1\n"),
+        None
+    );
+}
+
+#[derive(Deserialize)]
+struct LfsBatch {
+    objects: Vec<LfsObject>,
+}
+
+#[derive(Deserialize)]
+struct LfsObject {
+    actions: Option<LfsActions>,
+    error: Option<LfsError>,
+}
+
+#[derive(Deserialize)]
+struct LfsActions {
+    download: LfsAction,
+}
+
+#[derive(Deserialize)]
+struct LfsAction {
+    href: String,
+    #[serde(default)]
+    header: HashMap<String, String>,
+}
+
+#[derive(Deserialize)]
+struct LfsError {
+    message: String,
+}
+
+/// Download the object an LFS pointer refers to through the LFS batch API.
+pub async fn resolve_lfs(ss: &SharedState, repo: &str, pointer: &LfsPointer) -> Result<String> {
+    let mut req = ss
+        .req
+        .post(format!(
+            "https://github.com/{repo}.git/info/lfs/objects/batch"
+        ))
+        .header("Accept", "application/vnd.git-lfs+json")
+        .header("Content-Type", "application/vnd.git-lfs+json")
+        .body(
+            json!({
+                "operation": "download",
+                "transfers": ["basic"],
+                "objects": [{ "oid": pointer.oid, "size": pointer.size }],
+            })
+            .to_string(),
+        );
+    if let Some(token) = &ss.github_token {
+        req = req.basic_auth("x-access-token", Some(token));
+    }
+
+    let batch = req
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<LfsBatch>()
+        .await?;
+    let Some(object) = batch.objects.into_iter().next() else {
+        bail!("no objects in LFS batch response");
+    };
+    if let Some(error) = object.error {
+        bail!("LFS object unavailable: {}", error.message);
+    }
+    let Some(actions) = object.actions else {
+        bail!("LFS object has no download action");
+    };
+
+    let mut req = ss.req.get(&actions.download.href);
+    for (name, value) in &actions.download.header {
+        req = req.header(name, value);
+    }
+    let body = req.send().await?.error_for_status()?.bytes().await?;
+    if body.len() as u64 != pointer.size {
+        bail!(
+            "LFS object is {} bytes, expected {}",
+            body.len(),
+            pointer.size
+        );
+    }
+    Ok(transform::decode(body.to_vec())?)
+}
+
+#[derive(Deserialize)]
+pub struct Contents {
+    pub sha: String,
+    content: String,
+}
+
+impl Contents {
+    pub fn decode(&self) -> Result<Vec<u8>> {
+        // the content API wraps its base64 output
+        Ok(STANDARD.decode(self.content.replace('\n', ""))?)
+    }
+}
+
+#[derive(Deserialize)]
+struct Object {
+    sha: String,
+}
+
+#[derive(Deserialize)]
+struct Ref {
+    object: Object,
+}
+
+/// Get a file at some ref, or `None` if it doesn't exist there.
+pub async fn get_contents(
+    ss: &SharedState,
+    repo: &str,
+    path: &str,
+    ref_: &str,
+) -> Result<Option<Contents>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/contents/{path}"),
+    )
+    .query(&[("ref", ref_)]);
+    let res = send(ss, req).await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(res.error_for_status()?.json().await?))
+}
+
+#[derive(Deserialize)]
+struct LicenseInfo {
+    spdx_id: String,
+}
+
+#[derive(Deserialize)]
+struct RepoLicense {
+    license: LicenseInfo,
+}
+
+/// The SPDX identifier of the license GitHub detects for a repository at some ref, or `None` if
+/// it has no license file. Licenses GitHub can't identify come back as `NOASSERTION`.
+pub async fn repo_license(ss: &SharedState, repo: &str, ref_: &str) -> Result<Option<String>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/license"),
+    )
+    .query(&[("ref", ref_)]);
+    let res = send(ss, req).await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    let license: RepoLicense = res.error_for_status()?.json().await?;
+    Ok(Some(license.license.spdx_id))
+}
+
+#[derive(Deserialize)]
+struct DirEntry {
+    path: String,
+    #[serde(rename = "type")]
+    type_: String,
+}
+
+/// Paths of the files directly in a directory, empty if there is no such directory.
+pub async fn list_dir(ss: &SharedState, repo: &str, dir: &str, ref_: &str) -> Result<Vec<String>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/contents/{dir}"),
+    )
+    .query(&[("ref", ref_)]);
+    let res = send(ss, req).await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(Vec::new());
+    }
+    let entries: Vec<DirEntry> = res.error_for_status()?.json().await?;
+    Ok(entries
+        .into_iter()
+        .filter(|e| e.type_ == "file")
+        .map(|e| e.path)
+        .collect())
+}
+
+/// The commit a branch points to, or `None` if there is no such branch.
+pub async fn branch_head(ss: &SharedState, repo: &str, branch: &str) -> Result<Option<String>> {
+    let req = request(
+        ss,
+        Method::GET,
+        &format!("https://api.github.com/repos/{repo}/git/ref/heads/{branch}"),
+    );
+    let res = send(ss, req).await?;
+
+    if res.status() == StatusCode::NOT_FOUND {
+        return Ok(None);
+    }
+    Ok(Some(
+        res.error_for_status()?.json::<Ref>().await?.object.sha,
+    ))
+}
+
+pub async fn create_branch(ss: &SharedState, repo: &str, branch: &str, sha: &str) -> Result<()> {
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/git/refs"),
+    )
+    .json(&json!({
+        "ref": format!("refs/heads/{branch}"),
+        "sha": sha,
+    }));
+    send(ss, req).await?.error_for_status()?;
+    Ok(())
+}
+
+/// Commit a new version of a file to a branch. `sha` is the blob being replaced, if any.
+pub async fn put_contents(
+    ss: &SharedState,
+    repo: &str,
+    path: &str,
+    branch: &str,
+    message: &str,
+    content: &[u8],
+    sha: Option<&str>,
+) -> Result<()> {
+    let req = request(
+        ss,
+        Method::PUT,
+        &format!("https://api.github.com/repos/{repo}/contents/{path}"),
+    )
+    .json(&json!({
+        "message": message,
+        "content": STANDARD.encode(content),
+        "sha": sha,
+        "branch": branch,
+    }));
+    send(ss, req).await?.error_for_status()?;
+    Ok(())
+}
@@ -0,0 +1,83 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use color_eyre::eyre::{Result, bail};
+use tracing::{info, warn};
+
+use crate::{SharedState, set_period};
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+}
+
+/// `3d 4h 5m`, for uptimes.
+fn format_uptime(uptime: Duration) -> String {
+    let mins = uptime.as_secs() / 60;
+    format!("{}d {}h {}m", mins / (24 * 60), mins / 60 % 24, mins % 60)
+}
+
+/// Overwrite a wiki's status page with what we're up to.
+async fn update(ss: &SharedState, wiki: &str, page: &str) -> Result<()> {
+    let mappings = ss
+        .map
+        .load()
+        .iter()
+        .filter(|(source, _)| source.wiki == wiki)
+        .map(|(_, targets)| targets.len())
+        .sum::<usize>();
+    let last_sync = ss
+        .audit
+        .last_success(wiki)?
+        .unwrap_or_else(|| "never".to_owned());
+    let uptime = ss.started.elapsed().unwrap_or_default();
+    let text = format!(
+        "* Version: {}\n\
+         * Running since: {} ({})\n\
+         * Mappings: {mappings}\n\
+         * Last successful sync: {last_sync}\n\
+         * Updated: {}\n",
+        env!("CARGO_PKG_VERSION"),
+        httpdate::fmt_http_date(ss.started),
+        format_uptime(uptime),
+        httpdate::fmt_http_date(SystemTime::now()),
+    );
+
+    let res = crate::wp::save(&ss.client(wiki), page, &text, "Updating status").await?;
+    if let Some(e) = res.error {
+        bail!("{}: {}", e.code, e.info);
+    }
+    Ok(())
+}
+
+pub async fn task(cx: Context) {
+    let mut int =
+        tokio::time::interval(Duration::from_secs(cx.ss.config().heartbeat_interval_secs));
+
+    loop {
+        int.tick().await;
+        set_period(&mut int, cx.ss.config().heartbeat_interval_secs);
+
+        for (wiki, config) in &cx.ss.config().wikis {
+            let Some(page) = &config.status_page else {
+                continue;
+            };
+            match update(&cx.ss, wiki, page).await {
+                Ok(()) => info!(%wiki, "updated status page"),
+                Err(e) => warn!(?e, %wiki, "couldn't update status page"),
+            }
+        }
+    }
+}
+
+pub fn start(cx: Context) {
+    tokio::spawn(task(cx));
+}
+
+#[test]
+fn test_format_uptime() {
+    assert_eq!(format_uptime(Duration::from_secs(59)), "0d 0h 0m");
+    assert_eq!(
+        format_uptime(Duration::from_secs(((2 * 24 + 3) * 60 + 4) * 60)),
+        "2d 3h 4m"
+    );
+}
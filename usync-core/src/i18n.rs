@@ -0,0 +1,133 @@
+//! Syncing a directory of `<lang>.json` translations to language subpages of a synced page.
+
+use std::sync::Arc;
+
+use tracing::{error, info, instrument, warn};
+
+use crate::github::{Fetched, get_file, list_dir, repo_name};
+use crate::license;
+use crate::parser::Target;
+use crate::schedule::Tier;
+use crate::updater::edit_summary;
+use crate::validate;
+use crate::wp::{self, EditResponse};
+use crate::{GitHubPush, SharedState};
+
+/// Language of a translation file directly in `dir`, e.g. `de` for `i18n/de.json`.
+pub fn language<'a>(dir: &str, path: &'a str) -> Option<&'a str> {
+    let lang = path
+        .strip_prefix(dir)?
+        .strip_prefix('/')?
+        .strip_suffix(".json")?;
+    (!lang.is_empty() && !lang.contains('/')).then_some(lang)
+}
+
+/// Sync every translation to `<title>/<lang>.json` once a push changes any of them, creating
+/// subpages for new languages. Returns whether all of them went through.
+#[instrument(skip_all, fields(sync_id = %push.sync_id, %wiki, title = %target.title))]
+pub async fn sync(ss: Arc<SharedState>, push: GitHubPush, wiki: String, target: Target) -> bool {
+    let Some(dir) = &target.i18n else {
+        return true;
+    };
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return true;
+    };
+
+    // subpages already up to date are left alone when saving
+    let files = match list_dir(&ss, repo, dir, &push.after).await {
+        Ok(files) => files,
+        Err(e) => {
+            error!(?e, %dir, "couldn't list translations");
+            return false;
+        }
+    };
+
+    let summary = edit_summary(&ss, &wiki, &push);
+    let mut ok = true;
+    for path in &files {
+        let Some(lang) = language(dir, path) else {
+            continue;
+        };
+        let subpage = format!("{}/{lang}.json", target.title);
+        ok &= sync_file(&ss, &push, repo, path, &wiki, &subpage, &summary).await;
+    }
+    ok
+}
+
+async fn sync_file(
+    ss: &SharedState,
+    push: &GitHubPush,
+    repo: &str,
+    path: &str,
+    wiki: &str,
+    subpage: &str,
+    summary: &str,
+) -> bool {
+    let url = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let text = match get_file(ss, &url, &push.after, None).await {
+        Ok(Fetched::Content { text, .. }) => text,
+        Ok(Fetched::NotModified) => return true,
+        Err(e) => {
+            error!(?e, %path, "couldn't get translation");
+            return false;
+        }
+    };
+    if let Err(e) = validate::json(&text, None) {
+        warn!(%e, %path, "refusing to deploy a translation that doesn't parse");
+        return false;
+    }
+    if license::check(ss, repo, &push.after, &text).await.is_err() {
+        return false;
+    }
+    save_subpage(ss, wiki, subpage, &text, summary, Tier::of(push)).await
+}
+
+/// Save a subpage generated from the repository, unless it already has that content.
+pub async fn save_subpage(
+    ss: &SharedState,
+    wiki: &str,
+    subpage: &str,
+    text: &str,
+    summary: &str,
+    tier: Tier,
+) -> bool {
+    let staged = ss.config().staging.redirect(wiki, subpage);
+    let (wiki, subpage) = match &staged {
+        Some((wiki, subpage)) => (&**wiki, &**subpage),
+        None => (wiki, subpage),
+    };
+    let client = ss.client(wiki);
+    match wp::content(&client, subpage).await {
+        Ok(Some(current)) if current.trim_end() == text.trim_end() => return true,
+        Ok(_) => {}
+        Err(e) => {
+            error!(?e, %subpage, "couldn't fetch subpage");
+            return false;
+        }
+    }
+
+    let _permit = ss.sync_permits.acquire(tier).await;
+    match wp::save(&client, subpage, text, summary).await {
+        Ok(EditResponse { error: None, .. }) => {
+            info!(%subpage, "deployed subpage");
+            true
+        }
+        Ok(EditResponse { error: Some(e), .. }) => {
+            warn!(?e, %subpage, "couldn't save subpage");
+            false
+        }
+        Err(e) => {
+            error!(?e, %subpage, "couldn't save subpage");
+            false
+        }
+    }
+}
+
+#[test]
+fn test_language() {
+    assert_eq!(language("i18n", "i18n/de.json"), Some("de"));
+    assert_eq!(language("i18n", "i18n/zh-hant.json"), Some("zh-hant"));
+    assert_eq!(language("i18n", "i18n/nested/de.json"), None);
+    assert_eq!(language("i18n", "i18n.json"), None);
+    assert_eq!(language("i18n", "src/i18n/de.json"), None);
+}
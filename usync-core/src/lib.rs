@@ -0,0 +1,1141 @@
+//! Deploys files from GitHub to MediaWiki pages that ask for them with a sync header.
+//!
+//! [`start`] logs in and builds the sync map, after which [`Bot::serve`] runs the webhook server
+//! and background tasks. The pieces are usable on their own: [`updater::parse_header`] reads
+//! sync headers, [`Push::into_edit_summary`] builds edit summaries from [`GitHubPush`] payloads,
+//! and [`updater::deploy`] runs the sync pipeline for a single push.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{App, HttpRequest, HttpResponse, HttpServer, Responder, get, post, web};
+use arc_swap::ArcSwap;
+use audit::{Audit, HistoryQuery};
+use auth::Secrets;
+use color_eyre::eyre::bail;
+use config::Config;
+use events::Activity;
+use metrics::Metrics;
+use parser::{Reparse, Report, SyncMap, SyncSource, Target};
+use rename::Renamed;
+use reverse::WikiEdit;
+use schedule::Permits;
+use serde::{Deserialize, Serialize};
+use tokio::signal::unix::{SignalKind, signal};
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::{self, Receiver, Sender};
+use tokio::sync::{Notify, broadcast, watch};
+use tokio::task::{JoinError, JoinHandle};
+use tokio::time::{Instant, Interval};
+use tracing::{error, info, warn};
+
+pub mod admin;
+pub mod audit;
+pub mod auth;
+pub mod config;
+pub mod deploylog;
+pub mod discord;
+pub mod docs;
+pub mod events;
+pub mod expand;
+pub mod feed;
+pub mod gadgets;
+pub mod github;
+pub mod heartbeat;
+pub mod i18n;
+pub mod license;
+pub mod messages;
+pub mod metrics;
+pub mod mirror;
+pub mod parser;
+pub mod reconcile;
+pub mod removed;
+pub mod rename;
+pub mod reverse;
+pub mod schedule;
+pub mod selfcheck;
+pub mod stream;
+pub mod systemd;
+pub mod toolforge;
+pub mod transform;
+pub mod ui;
+pub mod updater;
+pub mod validate;
+pub mod wp;
+
+pub struct SharedState {
+    /// swapped out as a whole when the config is reloaded
+    config: RwLock<Arc<Config>>,
+    /// published whole by the parser, so readers never wait on a rebuild
+    map: ArcSwap<SyncMap>,
+    /// conflicts and refused registrations found while building the map
+    report: Mutex<Report>,
+    /// logged in API clients, by wiki name
+    clients: HashMap<String, Mutex<w::Client>>,
+    /// who we are logged in as, by wiki name
+    users: HashMap<String, String>,
+    github_token: Option<String>,
+    admin_token: Option<String>,
+    /// ETag of the file last confirmed on each (wiki, title)
+    etags: Mutex<HashMap<(String, String), String>>,
+    /// recent edits to synced pages not made by us
+    external_edits: Mutex<VecDeque<WikiEdit>>,
+    req: reqwest::Client,
+    /// repositories that moved, by old URL
+    renames: Mutex<HashMap<String, Renamed>>,
+    /// edits to a wiki are held until this instant while it is read-only
+    paused_until: Mutex<HashMap<String, Instant>>,
+    /// notified whenever the parser finishes a full rebuild of the map
+    rebuilt: watch::Sender<()>,
+    /// when the map was last rebuilt in full
+    rebuilt_at: Mutex<Option<Instant>>,
+    /// asks the parser to reparse pages, e.g. ones that turned out to be gone
+    reparse: Sender<Reparse>,
+    metrics: Metrics,
+    audit: Audit,
+    /// lines waiting to be appended to each wiki's deployment log page
+    deploy_log: Mutex<HashMap<String, Vec<String>>>,
+    /// sync activity, streamed on `/events`
+    events: broadcast::Sender<Activity>,
+    /// limits how many pages are synced at once, live syncs first
+    sync_permits: Permits,
+    /// pushes waiting out the coalescing window, by wiki and title
+    pending: Mutex<updater::Pending>,
+    /// pages edited on the wikis, waiting to be reparsed, by wiki and title. Kept apart from
+    /// the reparse queue so a busy wiki can't crowd out rebuilds
+    edited: Mutex<HashSet<(String, String)>>,
+    /// wakes the parser when `edited` gets a page
+    edited_notify: Notify,
+    /// pages fetched in a batch ahead of their syncs, by wiki and title
+    prefetched: Mutex<HashMap<(String, String), (Instant, wp::Page)>>,
+    /// wiki usernames of GitHub accounts, by wiki name and login
+    usernames: Mutex<HashMap<String, HashMap<String, String>>>,
+    /// when we started, for the status page
+    started: SystemTime,
+}
+
+impl SharedState {
+    pub fn config(&self) -> Arc<Config> {
+        self.config.read().unwrap().clone()
+    }
+
+    /// Re-read config.toml, keeping the settings that can only change with a restart.
+    pub fn reload_config(&self) -> color_eyre::Result<()> {
+        let mut config = config::read()?;
+        for warning in config.validate()? {
+            warn!("{warning}");
+        }
+        let mut current = self.config.write().unwrap();
+        config.keep_structure(&current)?;
+        *current = Arc::new(config);
+        Ok(())
+    }
+
+    /// Have the parser look at a page that was edited, once however often it's edited before
+    /// the parser gets to it.
+    pub fn page_edited(&self, wiki: &str, title: &str) {
+        let mut edited = self.edited.lock().unwrap();
+        if edited.insert((wiki.to_owned(), title.to_owned())) {
+            self.edited_notify.notify_one();
+        }
+    }
+
+    /// Where a page is synced from, going by the current map.
+    pub fn mapping(&self, wiki: &str, title: &str) -> Option<(SyncSource, Target)> {
+        parser::find_mapping(&self.map.load(), wiki, title)
+    }
+
+    /// Like [`mapping`](Self::mapping), but also finds pages asked for under a title the wiki
+    /// normalizes or redirects.
+    pub async fn resolve_mapping(&self, wiki: &str, title: &str) -> Option<(SyncSource, Target)> {
+        if let Some(found) = self.mapping(wiki, title) {
+            return Some(found);
+        }
+        // an unknown wiki has no client to ask
+        if !self.clients.contains_key(wiki) {
+            return None;
+        }
+        let page = wp::fetch_page(&self.client(wiki), title).await.ok()?;
+        self.mapping(wiki, &page.title)
+    }
+
+    /// The API client for a wiki. Clients may be swapped out when access tokens are refreshed.
+    fn client(&self, wiki: &str) -> w::Client {
+        self.clients[wiki].lock().unwrap().clone()
+    }
+
+    /// The current URL of a repository, following recent renames and transfers.
+    fn canonical_repo(&self, repo: &str) -> String {
+        match self.renames.lock().unwrap().get(repo) {
+            Some(renamed) if renamed.until > Instant::now() => renamed.to.clone(),
+            _ => repo.to_owned(),
+        }
+    }
+}
+
+pub struct State {
+    sort: Sender<GitHubPush>,
+    reparse: Sender<Reparse>,
+    shared: Arc<SharedState>,
+}
+
+pub enum Commits {
+    /// commit message
+    Single(String),
+    /// number of commits
+    Multiple(usize),
+}
+
+pub struct Push {
+    pub commits: Commits,
+    pub authors: Vec<String>,
+    pub url: String,
+}
+
+impl Push {
+    /// The edit summary in a wiki's language, falling back to English.
+    pub fn into_edit_summary(self, prefix: &str, lang: &str) -> String {
+        let author = match &*self.authors {
+            // the payload's commit list can be empty when GitHub truncated it
+            [] => None,
+            list if list.len() <= 3 => Some(list.join(", ")),
+            [first, rest @ ..] => Some(messages::others(lang, first, rest.len())),
+        };
+
+        let commit = match self.commits {
+            Commits::Single(msg) => msg,
+            Commits::Multiple(n) => messages::commits(lang, n),
+        };
+
+        let summary = match author {
+            Some(author) => format!("{author}: {commit} ({})", self.url),
+            None => messages::update(lang, &self.url),
+        };
+        if prefix.is_empty() {
+            summary
+        } else {
+            format!("{prefix} {summary}")
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GitHubAuthor {
+    pub name: String,
+    /// GitHub login, if the commit is linked to an account
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub username: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GitHubCommit {
+    pub author: GitHubAuthor,
+    pub committer: GitHubAuthor,
+    pub message: String,
+    pub added: Vec<String>,
+    pub modified: Vec<String>,
+    #[serde(default)]
+    pub removed: Vec<String>,
+    #[serde(default)]
+    pub url: String,
+}
+
+impl GitHubCommit {
+    /// Whether the message contains `marker`, e.g. `[skip sync]`, ignoring case.
+    pub fn has_marker(&self, marker: &str) -> bool {
+        self.message.to_lowercase().contains(&marker.to_lowercase())
+    }
+
+    /// Whether the message asks for the commit not to be deployed.
+    fn skips_sync(&self) -> bool {
+        skips_sync(&self.message)
+    }
+}
+
+/// Whether a commit message asks for the commit not to be deployed.
+pub(crate) fn skips_sync(message: &str) -> bool {
+    let message = message.to_lowercase();
+    message.contains("[skip sync]") || message.contains("[skip usync]")
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct Repository {
+    pub html_url: String,
+    pub contents_url: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct GitHubPush {
+    /// identifies this push in logs and the audit log, across tasks and retries
+    #[serde(default)]
+    pub sync_id: String,
+    pub compare: String,
+    pub commits: Vec<GitHubCommit>,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+    /// the commit the ref pointed to before the push
+    #[serde(default)]
+    pub before: String,
+    /// the commit the ref points to after the push
+    pub after: String,
+    /// history was rewritten, so `commits` and `compare` can't be trusted
+    #[serde(default)]
+    pub forced: bool,
+    pub head_commit: Option<GitHubCommit>,
+    pub repository: Repository,
+    #[serde(skip, default)]
+    pub retry: bool,
+    /// every file changed by the push, from the compare API, when the payload's commit list is
+    /// incomplete
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub changed: Option<Vec<String>>,
+    /// set when deploying artifacts of a workflow run rather than files of a push
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub run: Option<WorkflowRun>,
+    /// set on pushes from reconciliation, which look at every page rather than trusting ETags
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drift: Option<reconcile::Drift>,
+    /// an admin confirmed deploying this even though it shrinks the page a lot
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub confirmed: bool,
+    /// the account that pushed, if GitHub told us
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sender: Option<Actor>,
+    /// go through every check but don't edit, e.g. when replaying a payload locally
+    #[serde(skip, default)]
+    pub dry_run: bool,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Actor {
+    pub login: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorkflowRun {
+    pub name: String,
+    pub run_number: u64,
+    pub html_url: String,
+    pub artifacts_url: String,
+    pub head_branch: String,
+    pub head_sha: String,
+    pub conclusion: Option<String>,
+    pub actor: Actor,
+}
+
+#[derive(Deserialize)]
+struct WorkflowRunEvent {
+    action: String,
+    workflow_run: WorkflowRun,
+    repository: Repository,
+    sender: Option<Actor>,
+}
+
+impl GitHubPush {
+    /// Whether the head commit, or every commit, asks for the push not to be deployed.
+    pub fn skips_sync(&self) -> bool {
+        self.head_commit
+            .as_ref()
+            .is_some_and(GitHubCommit::skips_sync)
+            || (!self.commits.is_empty() && self.commits.iter().all(GitHubCommit::skips_sync))
+    }
+
+    /// Whether the push added or modified `path`.
+    fn touches(&self, path: &str) -> bool {
+        // the commits listed for a force push don't tell us what changed relative to the
+        // deployed version, so check every file against the wiki
+        if self.forced {
+            return true;
+        }
+        match &self.changed {
+            Some(changed) => changed.iter().any(|p| p == path),
+            None => self
+                .commits
+                .iter()
+                .any(|c| c.added.iter().any(|p| p == path) || c.modified.iter().any(|p| p == path)),
+        }
+    }
+
+    /// Whether the push removed `path`, going by the last commit that touched it.
+    pub fn removes(&self, path: &str) -> bool {
+        // a force push's commits don't say what happened relative to the deployed version
+        if self.forced {
+            return false;
+        }
+        self.commits
+            .iter()
+            .rev()
+            .find_map(|c| {
+                if c.removed.iter().any(|p| p == path) {
+                    Some(true)
+                } else {
+                    c.added
+                        .iter()
+                        .chain(&c.modified)
+                        .any(|p| p == path)
+                        .then_some(false)
+                }
+            })
+            .unwrap_or(false)
+    }
+
+    /// GitHub caps the commits listed in a push payload and leaves out file lists for very
+    /// large commits, so the payload alone can miss changes.
+    fn maybe_truncated(&self) -> bool {
+        self.run.is_none()
+            && !self.forced
+            && !self.before.is_empty()
+            && self.before.bytes().any(|b| b != b'0')
+            && (self.commits.len() >= 20
+                || (self.commits.is_empty() && self.before != self.after)
+                || self
+                    .commits
+                    .iter()
+                    .any(|c| c.added.is_empty() && c.modified.is_empty() && c.removed.is_empty()))
+    }
+
+    /// Whether the push added or modified anything directly in `dir`.
+    pub fn touches_dir(&self, dir: &str) -> bool {
+        self.forced
+            || self
+                .files()
+                .iter()
+                .any(|f| i18n::language(dir, f).is_some())
+    }
+
+    /// Files added or modified, going by `changed` if we have it.
+    pub fn files(&self) -> Vec<String> {
+        match &self.changed {
+            Some(changed) => changed.clone(),
+            None => self
+                .commits
+                .iter()
+                .flat_map(|c| c.added.iter().chain(&c.modified))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Fold a later push to the same ref into this one, as if both were pushed at once.
+    fn merge(&mut self, later: GitHubPush) {
+        if later.run.is_some() {
+            // artifacts are built from scratch, only the latest run matters
+            *self = GitHubPush {
+                sync_id: std::mem::take(&mut self.sync_id),
+                ..later
+            };
+            return;
+        }
+
+        if self.changed.is_some() || later.changed.is_some() {
+            let mut changed = self.files();
+            changed.extend(later.files());
+            changed.sort();
+            changed.dedup();
+            self.changed = Some(changed);
+        }
+        self.commits.extend(later.commits);
+        self.forced |= later.forced;
+        // a real push has to be deployed, not just reported
+        self.drift = self.drift.and(later.drift);
+        self.confirmed &= later.confirmed;
+        self.dry_run &= later.dry_run;
+        // we can only vouch for a single pusher
+        if self.sender != later.sender {
+            self.sender = None;
+        }
+        self.after = later.after;
+        self.head_commit = later.head_commit.or(self.head_commit.take());
+        self.compare = if self.before.is_empty() {
+            later.compare
+        } else {
+            format!(
+                "{}/compare/{}...{}",
+                self.repository.html_url, self.before, self.after
+            )
+        };
+    }
+}
+
+#[test]
+fn test_merge() {
+    let push = |before: &str, after: &str, file: &str| -> GitHubPush {
+        serde_json::from_value(serde_json::json!({
+            "compare": "",
+            "commits": [{
+                "author": { "name": "a" },
+                "committer": { "name": "a" },
+                "message": after,
+                "added": [],
+                "modified": [file],
+            }],
+            "ref": "refs/heads/main",
+            "before": before,
+            "after": after,
+            "head_commit": null,
+            "repository": {
+                "html_url": "https://github.com/fee1-dead/usync",
+                "contents_url": "",
+            },
+        }))
+        .unwrap()
+    };
+    let mut first = push("a", "b", "x.js");
+    assert!(!first.skips_sync());
+    first.merge(push("b", "c", "y.js"));
+    assert_eq!(first.after, "c");
+    assert_eq!(first.commits.len(), 2);
+    assert!(first.touches("x.js") && first.touches("y.js"));
+    assert_eq!(
+        first.compare,
+        "https://github.com/fee1-dead/usync/compare/a...c"
+    );
+}
+
+#[test]
+fn test_skips_sync() {
+    let commit = |message: &str| GitHubCommit {
+        author: GitHubAuthor {
+            name: "a".to_owned(),
+            username: None,
+        },
+        committer: GitHubAuthor {
+            name: "a".to_owned(),
+            username: None,
+        },
+        message: message.to_owned(),
+        added: Vec::new(),
+        modified: vec!["a.js".to_owned()],
+        removed: Vec::new(),
+        url: String::new(),
+    };
+    assert!(commit("Reformat [Skip Sync]").skips_sync());
+    assert!(commit("[skip usync] bump deps").skips_sync());
+    assert!(!commit("fix the [skip] button").skips_sync());
+}
+
+#[test]
+fn test_removes() {
+    let push = |commits: serde_json::Value| -> GitHubPush {
+        serde_json::from_value(serde_json::json!({
+            "compare": "",
+            "commits": commits,
+            "ref": "refs/heads/main",
+            "before": "a",
+            "after": "b",
+            "head_commit": null,
+            "repository": {"html_url": "", "contents_url": ""},
+        }))
+        .unwrap()
+    };
+    let commit = |added: &[&str], removed: &[&str]| {
+        serde_json::json!({
+            "author": {"name": "a"},
+            "committer": {"name": "a"},
+            "message": "",
+            "added": added,
+            "modified": [],
+            "removed": removed,
+        })
+    };
+    let removed = push(serde_json::json!([commit(&[], &["a.js"])]));
+    assert!(removed.removes("a.js"));
+    assert!(!removed.removes("b.js"));
+    // added back later in the same push
+    let readded = push(serde_json::json!([
+        commit(&[], &["a.js"]),
+        commit(&["a.js"], &[])
+    ]));
+    assert!(!readded.removes("a.js"));
+}
+
+impl WorkflowRunEvent {
+    /// Treat a successful run as a push of its artifacts to the branch it ran on.
+    fn into_push(self) -> Option<GitHubPush> {
+        if self.action != "completed" || self.workflow_run.conclusion.as_deref() != Some("success")
+        {
+            return None;
+        }
+        Some(GitHubPush {
+            sync_id: String::new(),
+            compare: self.workflow_run.html_url.clone(),
+            commits: Vec::new(),
+            ref_: format!("refs/heads/{}", self.workflow_run.head_branch),
+            before: String::new(),
+            after: self.workflow_run.head_sha.clone(),
+            forced: false,
+            head_commit: None,
+            repository: self.repository,
+            retry: false,
+            changed: None,
+            run: Some(self.workflow_run),
+            drift: None,
+            confirmed: false,
+            dry_run: false,
+            sender: self.sender,
+        })
+    }
+}
+
+/// `repository_dispatch` event type that triggers a deploy
+const DISPATCH_EVENT: &str = "usync-deploy";
+
+#[derive(Deserialize)]
+struct DispatchPayload {
+    #[serde(rename = "ref")]
+    ref_: String,
+    path: String,
+    /// commit to deploy from, defaults to the head of `ref`
+    sha: Option<String>,
+    message: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DispatchEvent {
+    /// the event type
+    action: String,
+    client_payload: DispatchPayload,
+    repository: Repository,
+    sender: Actor,
+}
+
+impl DispatchEvent {
+    /// Treat a dispatch as a push modifying the requested file.
+    fn into_push(self) -> Option<GitHubPush> {
+        if self.action != DISPATCH_EVENT {
+            return None;
+        }
+        let payload = self.client_payload;
+        let ref_ = if payload.ref_.starts_with("refs/") {
+            payload.ref_
+        } else {
+            format!("refs/heads/{}", payload.ref_)
+        };
+        // filled in with the branch head before the push is queued
+        let after = payload.sha.unwrap_or_default();
+        let author = GitHubAuthor {
+            name: self.sender.login.clone(),
+            username: Some(self.sender.login.clone()),
+        };
+        Some(GitHubPush {
+            sync_id: String::new(),
+            compare: format!("{}/tree/{after}", self.repository.html_url),
+            commits: vec![GitHubCommit {
+                author: author.clone(),
+                committer: author,
+                message: payload
+                    .message
+                    .unwrap_or_else(|| "manual deploy".to_owned()),
+                added: Vec::new(),
+                modified: vec![payload.path],
+                removed: Vec::new(),
+                url: String::new(),
+            }],
+            ref_,
+            before: String::new(),
+            after,
+            forced: false,
+            head_commit: None,
+            repository: self.repository,
+            retry: false,
+            changed: None,
+            run: None,
+            drift: None,
+            confirmed: false,
+            dry_run: false,
+            sender: Some(self.sender),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+struct NameChange {
+    from: String,
+}
+
+#[derive(Deserialize)]
+struct OwnerChange {
+    user: Option<Actor>,
+    organization: Option<Actor>,
+}
+
+#[derive(Deserialize)]
+struct OwnerFrom {
+    from: OwnerChange,
+}
+
+#[derive(Deserialize, Default)]
+struct RepositoryChanges {
+    repository: Option<RepositoryNameChange>,
+    owner: Option<OwnerFrom>,
+}
+
+#[derive(Deserialize)]
+struct RepositoryNameChange {
+    name: NameChange,
+}
+
+#[derive(Deserialize)]
+struct MovedRepository {
+    html_url: String,
+    name: String,
+    owner: Actor,
+}
+
+#[derive(Deserialize)]
+struct RepositoryEvent {
+    action: String,
+    #[serde(default)]
+    changes: RepositoryChanges,
+    repository: MovedRepository,
+}
+
+impl RepositoryEvent {
+    /// The old and new URL of a renamed or transferred repository.
+    fn moved(self) -> Option<(String, String)> {
+        let repo = self.repository;
+        let from = match &*self.action {
+            "renamed" => format!(
+                "https://github.com/{}/{}",
+                repo.owner.login, self.changes.repository?.name.from
+            ),
+            "transferred" => {
+                let owner = self.changes.owner?.from;
+                let owner = owner.user.or(owner.organization)?;
+                format!("https://github.com/{}/{}", owner.login, repo.name)
+            }
+            _ => return None,
+        };
+        Some((from, repo.html_url))
+    }
+}
+
+/// A fresh ID to correlate everything done for one push.
+pub fn new_sync_id() -> String {
+    static COUNTER: AtomicU64 = AtomicU64::new(0);
+    let started = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs();
+    let n = COUNTER.fetch_add(1, Ordering::Relaxed);
+    format!("{started:x}-{n}")
+}
+
+/// What a webhook asks of us.
+pub enum Webhook {
+    Push(Box<GitHubPush>),
+    /// a repository was renamed or transferred, from and to
+    Moved(String, String),
+    Ignored,
+}
+
+/// Parse a webhook payload by its `X-GitHub-Event`.
+pub fn parse_event(event: &str, body: &str) -> serde_json::Result<Webhook> {
+    let push = match event {
+        "push" => Some(serde_json::from_str::<GitHubPush>(body)?),
+        "workflow_run" => serde_json::from_str::<WorkflowRunEvent>(body)?.into_push(),
+        "repository" => {
+            let moved = serde_json::from_str::<RepositoryEvent>(body)?.moved();
+            return Ok(match moved {
+                Some((from, to)) => Webhook::Moved(from, to),
+                None => Webhook::Ignored,
+            });
+        }
+        "repository_dispatch" => serde_json::from_str::<DispatchEvent>(body)?.into_push(),
+        _ => None,
+    };
+    Ok(push.map_or(Webhook::Ignored, |push| Webhook::Push(Box::new(push))))
+}
+
+/// Point a push that didn't say which commit it's for at the head of its branch. Returns whether
+/// that worked out.
+pub async fn resolve_head(ss: &SharedState, push: &mut GitHubPush) -> bool {
+    let repo = &push.repository.html_url;
+    let (Some(name), Some(branch)) = (
+        github::repo_name(repo),
+        push.ref_.strip_prefix("refs/heads/"),
+    ) else {
+        warn!(%repo, ref_ = %push.ref_, "dropping a dispatch without a commit for a ref that isn't a branch");
+        return false;
+    };
+    match github::branch_head(ss, name, branch).await {
+        Ok(Some(head)) => {
+            push.compare = format!("{repo}/tree/{head}");
+            push.after = head;
+            true
+        }
+        Ok(None) => {
+            warn!(%repo, %branch, "dropping a dispatch for a branch that doesn't exist");
+            false
+        }
+        Err(e) => {
+            warn!(?e, %repo, %branch, "dropping a dispatch, couldn't get the branch head");
+            false
+        }
+    }
+}
+
+/// How long to tell GitHub to wait when the push queue is full.
+const BUSY_RETRY_AFTER_SECS: u64 = 30;
+
+#[post("/webhook")]
+async fn handle(state: web::Data<State>, req: HttpRequest, body: String) -> impl Responder {
+    let Some(val) = req
+        .headers()
+        .get("X-GitHub-Event")
+        .and_then(|v| v.to_str().ok())
+    else {
+        return HttpResponse::ImATeapot().finish();
+    };
+
+    let mut push = match parse_event(val, &body) {
+        Ok(Webhook::Push(push)) => *push,
+        Ok(Webhook::Moved(from, to)) => {
+            let ss = state.shared.clone();
+            let reparse = state.reparse.clone();
+            tokio::spawn(async move { rename::renamed(&ss, &reparse, from, to).await });
+            return HttpResponse::Ok().finish();
+        }
+        Ok(Webhook::Ignored) => return HttpResponse::Ok().finish(),
+        Err(_) => return HttpResponse::ImATeapot().finish(),
+    };
+
+    let repo = &push.repository.html_url;
+    if !state.shared.config().discovery.allows_repo(repo) {
+        warn!(%repo, ref_ = %push.ref_, "rejecting push from a repository that isn't allowed");
+        return HttpResponse::Forbidden().finish();
+    }
+
+    // a dispatch may leave the commit to us
+    if push.after.is_empty() && !resolve_head(&state.shared, &mut push).await {
+        return HttpResponse::BadGateway().finish();
+    }
+
+    push.sync_id = new_sync_id();
+    info!(sync_id = %push.sync_id, repo = %push.repository.html_url, ref_ = %push.ref_, "received webhook");
+    let received = Activity::WebhookReceived {
+        sync_id: push.sync_id.clone(),
+        repo: push.repository.html_url.clone(),
+        ref_: push.ref_.clone(),
+        sha: push.after.clone(),
+    };
+
+    if let Err(e) = state.shared.audit.begin(&push) {
+        warn!(?e, sync_id = %push.sync_id, "couldn't journal push");
+    }
+    match state.sort.try_send(push) {
+        Ok(()) => {}
+        Err(TrySendError::Full(push)) => {
+            let _ = state.shared.audit.finish(&push.sync_id);
+            // ask for a redelivery rather than losing the push
+            warn!(sync_id = %push.sync_id, "push queue is full, turning webhook away");
+            return HttpResponse::ServiceUnavailable()
+                .insert_header(("Retry-After", BUSY_RETRY_AFTER_SECS.to_string()))
+                .finish();
+        }
+        Err(TrySendError::Closed(push)) => {
+            let _ = state.shared.audit.finish(&push.sync_id);
+            tracing::error!("cannot send to sorter!");
+            return HttpResponse::InternalServerError().finish();
+        }
+    }
+
+    events::publish(&state.shared, received);
+
+    HttpResponse::Ok().finish()
+}
+
+#[get("/status")]
+async fn status(state: web::Data<State>) -> impl Responder {
+    let ss = &state.shared;
+    let mappings = ss.map.load().values().map(Vec::len).sum::<usize>();
+    let report = ss.report.lock().unwrap().clone();
+    let external_edits = ss.external_edits.lock().unwrap().clone();
+    let known = |n: i64| (n >= 0).then_some(n);
+    let metrics = &ss.metrics;
+
+    HttpResponse::Ok().json(serde_json::json!({
+        "mappings": mappings,
+        "conflicts": report.conflicts,
+        "rejected": report.rejected,
+        "external_edits": external_edits,
+        "github_ratelimit": {
+            "remaining": known(metrics.github_ratelimit_remaining.load(Ordering::Relaxed)),
+            "limit": known(metrics.github_ratelimit_limit.load(Ordering::Relaxed)),
+            "reset": known(metrics.github_ratelimit_reset.load(Ordering::Relaxed)),
+        },
+    }))
+}
+
+/// Past sync attempts, e.g. to find out when a commit reached the wiki.
+#[get("/history")]
+async fn history(state: web::Data<State>, query: web::Query<HistoryQuery>) -> impl Responder {
+    match state.shared.audit.history(&query) {
+        Ok(entries) => HttpResponse::Ok().json(entries),
+        Err(e) => {
+            warn!(?e, "couldn't query the audit log");
+            HttpResponse::InternalServerError().finish()
+        }
+    }
+}
+
+/// Whether we're up at all.
+#[get("/healthz")]
+async fn healthz() -> impl Responder {
+    HttpResponse::Ok().finish()
+}
+
+/// Whether we can sync right now, by dependency.
+#[get("/readyz")]
+async fn readyz(state: web::Data<State>) -> impl Responder {
+    let checks = selfcheck::readiness(&state.shared).await;
+    let mut res = if checks.values().all(|c| c.ok) {
+        HttpResponse::Ok()
+    } else {
+        HttpResponse::ServiceUnavailable()
+    };
+    res.json(checks)
+}
+
+#[get("/metrics")]
+async fn metrics_endpoint(state: web::Data<State>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type("text/plain; version=0.0.4")
+        .body(state.shared.metrics.render())
+}
+
+/// Make a periodic task follow a changed interval, starting from the next tick.
+fn set_period(int: &mut Interval, secs: u64) {
+    let period = Duration::from_secs(secs);
+    if int.period() != period {
+        *int = tokio::time::interval_at(Instant::now() + period, period);
+    }
+}
+
+/// Queue the pushes journaled by a previous process that it didn't get to finish.
+async fn resume(ss: &SharedState, send: &Sender<GitHubPush>) {
+    let pushes = match ss.audit.unfinished() {
+        Ok(pushes) => pushes,
+        Err(e) => {
+            error!(?e, "couldn't read journaled pushes");
+            return;
+        }
+    };
+    for push in pushes {
+        info!(sync_id = %push.sync_id, repo = %push.repository.html_url, "resuming push");
+        if send.send(push).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// A logged in bot with its sync map being built, ready to serve or run one-off commands.
+pub struct Bot {
+    pub ss: Arc<SharedState>,
+    /// changes once the parser has finished its first rebuild of the map
+    pub first_rebuild: watch::Receiver<()>,
+    sort_send: Sender<GitHubPush>,
+    update_recv: Receiver<GitHubPush>,
+    reparse_send: Sender<Reparse>,
+    parser: JoinHandle<()>,
+}
+
+/// Wait for the first of the tasks we can't work without to end, which they only do when
+/// they panic.
+async fn first_to_end(
+    tasks: Vec<(&'static str, JoinHandle<()>)>,
+) -> (&'static str, Result<(), JoinError>) {
+    let (names, handles): (Vec<_>, Vec<_>) = tasks.into_iter().unzip();
+    let (res, i, _) = futures_util::future::select_all(handles).await;
+    (names[i], res)
+}
+
+/// Log in to every wiki, check the setup and start discovering sync pages.
+pub async fn start(config: Config, secrets: Secrets) -> color_eyre::Result<Bot> {
+    let mut clients = HashMap::new();
+    let mut users = HashMap::new();
+    let mut refresh = Vec::new();
+    for name in config.wikis.keys() {
+        let cred = secrets.credential(name)?;
+        let (client, user, expires_in) = auth::login(&config, name, cred).await?;
+        clients.insert(name.clone(), Mutex::new(client));
+        users.insert(name.clone(), user);
+        if let Some(expires_in) = expires_in {
+            refresh.push((name.clone(), cred.clone(), expires_in));
+        }
+    }
+
+    let (sort_send, update_recv) = mpsc::channel(config.push_queue_size);
+    let (reparse_send, reparse_recv) = mpsc::channel(config.reparse_queue_size);
+    let req = reqwest::ClientBuilder::new()
+        .use_rustls_tls()
+        .user_agent(&config.user_agent)
+        .build()?;
+    let audit = Audit::open(&config)?;
+    let map = parser::load_map(&config);
+    info!(entries = map.len(), "loaded map snapshot");
+    let sync_permits = Permits::new(config.max_concurrent_syncs, config.max_background_syncs);
+    let metrics = Metrics::default();
+    metrics
+        .sync_limit
+        .store(config.max_concurrent_syncs as i64, Ordering::Relaxed);
+    let shared = Arc::new(SharedState {
+        config: RwLock::new(Arc::new(config)),
+        map: ArcSwap::from_pointee(map),
+        report: Mutex::new(Report::default()),
+        clients,
+        users,
+        github_token: secrets.github_token,
+        admin_token: secrets.admin_token,
+        external_edits: Mutex::new(VecDeque::new()),
+        usernames: Mutex::new(HashMap::new()),
+        started: SystemTime::now(),
+        etags: Mutex::new(HashMap::new()),
+        req,
+        renames: Mutex::new(HashMap::new()),
+        paused_until: Mutex::new(HashMap::new()),
+        rebuilt: watch::Sender::new(()),
+        rebuilt_at: Mutex::new(None),
+        reparse: reparse_send.clone(),
+        metrics,
+        audit,
+        deploy_log: Mutex::new(HashMap::new()),
+        events: broadcast::Sender::new(100),
+        sync_permits,
+        pending: Mutex::new(HashMap::new()),
+        edited: Mutex::new(HashSet::new()),
+        edited_notify: Notify::new(),
+        prefetched: Mutex::new(HashMap::new()),
+    });
+    for (name, cred, expires_in) in refresh {
+        auth::start_refresh(shared.clone(), name, cred, expires_in);
+    }
+
+    selfcheck::run(&shared).await?;
+
+    // subscribe before the parser can finish its first rebuild
+    let first_rebuild = shared.rebuilt.subscribe();
+    let parser = parser::start(parser::Context {
+        ss: shared.clone(),
+        reparse_recv,
+    });
+
+    Ok(Bot {
+        ss: shared,
+        first_rebuild,
+        sort_send,
+        update_recv,
+        reparse_send,
+        parser,
+    })
+}
+
+impl Bot {
+    /// Start every background task and serve webhooks until the server stops.
+    pub async fn serve(self) -> color_eyre::Result<()> {
+        let Bot {
+            ss: shared,
+            first_rebuild,
+            sort_send,
+            update_recv,
+            reparse_send,
+            parser,
+        } = self;
+        let data = web::Data::new(State {
+            sort: sort_send.clone(),
+            reparse: reparse_send.clone(),
+            shared: shared.clone(),
+        });
+
+        let updaterctx = updater::Context {
+            ss: shared.clone(),
+            reparse_request: reparse_send.clone(),
+            send: sort_send.clone(),
+            recv: update_recv,
+        };
+        let updater = updater::start(updaterctx);
+
+        let stream = stream::start(stream::Context {
+            ss: shared.clone(),
+            reparse_request: reparse_send,
+        });
+
+        // pick up where the last process left off, once we know what's mapped
+        let ss = shared.clone();
+        let send = sort_send.clone();
+        let mut rebuilt = first_rebuild.clone();
+        tokio::spawn(async move {
+            let _ = rebuilt.changed().await;
+            resume(&ss, &send).await;
+        });
+
+        reconcile::start(reconcile::Context {
+            ss: shared.clone(),
+            send: sort_send,
+        });
+
+        if shared.config().mirror.enabled {
+            mirror::start(mirror::Context { ss: shared.clone() });
+        }
+
+        // always running, since a reload may add a log or status page
+        deploylog::start(deploylog::Context { ss: shared.clone() });
+        heartbeat::start(heartbeat::Context { ss: shared.clone() });
+
+        let ss = shared.clone();
+        let mut hangup = signal(SignalKind::hangup())?;
+        tokio::spawn(async move {
+            while hangup.recv().await.is_some() {
+                match systemd::reloading(|| ss.reload_config()) {
+                    Ok(()) => info!("reloaded config"),
+                    Err(e) => error!(?e, "couldn't reload config, keeping the old one"),
+                }
+            }
+        });
+
+        let server = HttpServer::new(move || {
+            App::new()
+                .app_data(data.clone())
+                .service(handle)
+                .service(status)
+                .service(history)
+                .service(healthz)
+                .service(readyz)
+                .service(metrics_endpoint)
+                .service(admin::failed)
+                .service(admin::retry)
+                .service(admin::confirm)
+                .service(admin::reparse)
+                .service(admin::force_sync)
+                .service(admin::reload)
+                .service(admin::block)
+                .service(admin::blocked)
+                .service(admin::unblock)
+                .service(ui::dashboard)
+                .service(events::events)
+                .service(feed::feed)
+        })
+        .bind(&shared.config().bind)?
+        .run();
+
+        info!("started");
+        tokio::spawn(systemd::ready(first_rebuild));
+
+        // without these, pushes would be accepted and go nowhere. better to stop and be
+        // restarted, picking the journaled pushes back up
+        let critical = vec![("parser", parser), ("updater", updater), ("stream", stream)];
+        let server_handle = server.handle();
+        tokio::select! {
+            res = server => res?,
+            (task, res) = first_to_end(critical) => {
+                error!(task, ?res, "background task died, shutting down");
+                server_handle.stop(true).await;
+                bail!("the {task} task died");
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,83 @@
+//! Refusing to deploy files whose license the wikis can't take.
+
+use tracing::{error, warn};
+
+use crate::SharedState;
+use crate::github::{RateLimited, repo_license};
+use crate::updater::Outcome;
+
+/// The license a file declares, going by an `SPDX-License-Identifier` tag or a `@license` one
+/// near its top.
+pub fn declared(text: &str) -> Option<&str> {
+    text.lines().take(20).find_map(|line| {
+        let (_, rest) = line
+            .split_once("SPDX-License-Identifier:")
+            .or_else(|| line.split_once("@license "))?;
+        let id = rest
+            .trim()
+            .trim_end_matches("*/")
+            .trim_end_matches("]]")
+            .trim();
+        (!id.is_empty()).then_some(id)
+    })
+}
+
+#[test]
+fn test_declared() {
+    assert_eq!(
+        declared("// header\n// SPDX-License-Identifier: MIT\nfoo();"),
+        Some("MIT")
+    );
+    assert_eq!(
+        declared("/* header */\n/* SPDX-License-Identifier: MIT OR Apache-2.0 */"),
+        Some("MIT OR Apache-2.0")
+    );
+    assert_eq!(
+        declared("/**\n * @license CC-BY-SA-4.0\n */"),
+        Some("CC-BY-SA-4.0")
+    );
+    assert_eq!(declared("foo();"), None);
+}
+
+/// Check that the file about to be deployed may be, if the instance asks for that. Fails with
+/// the outcome of the sync if not.
+pub async fn check(ss: &SharedState, repo: &str, sha: &str, text: &str) -> Result<(), Outcome> {
+    let config = ss.config();
+    let licenses = &config.licenses;
+    if !licenses.enabled() {
+        return Ok(());
+    }
+
+    let license = match declared(text) {
+        Some(license) => Some(license.to_owned()),
+        None if licenses.from_repo => match repo_license(ss, repo, sha).await {
+            Ok(license) => license,
+            Err(e) => {
+                if let Some(RateLimited { retry_after }) = e.downcast_ref() {
+                    return Err(Outcome::RateLimited(*retry_after));
+                }
+                error!(?e, "couldn't get the repository's license");
+                return Err(Outcome::Failed(
+                    "couldn't get the repository's license".into(),
+                ));
+            }
+        },
+        None => None,
+    };
+    let allowed = licenses.allowed.join(", ");
+    match license {
+        Some(license) if licenses.allows(&license) => Ok(()),
+        Some(license) => {
+            warn!(%license, "refusing to deploy a file under a license we can't take");
+            Err(Outcome::Failed(format!(
+                "the file is under {license}, which isn't one of {allowed}"
+            )))
+        }
+        None => {
+            warn!("refusing to deploy a file without a license");
+            Err(Outcome::Failed(format!(
+                "the file doesn't declare a license, it needs one of {allowed}"
+            )))
+        }
+    }
+}
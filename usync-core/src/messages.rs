@@ -0,0 +1,81 @@
+//! Wording of edit summaries, by wiki content language.
+
+/// Templates for one language. `$1` and `$2` are replaced with the arguments.
+struct Catalog {
+    lang: &'static str,
+    /// number of commits
+    commits: &'static str,
+    /// first author, number of other authors
+    others: &'static str,
+    /// compare URL, when no authors are known
+    update: &'static str,
+}
+
+const CATALOGS: &[Catalog] = &[
+    Catalog {
+        lang: "en",
+        commits: "$1 commits",
+        others: "$1 and $2 others",
+        update: "update from $1",
+    },
+    Catalog {
+        lang: "de",
+        commits: "$1 Commits",
+        others: "$1 und $2 weitere",
+        update: "Aktualisierung von $1",
+    },
+    Catalog {
+        lang: "fr",
+        commits: "$1 commits",
+        others: "$1 et $2 autres",
+        update: "mise à jour depuis $1",
+    },
+    Catalog {
+        lang: "es",
+        commits: "$1 commits",
+        others: "$1 y $2 más",
+        update: "actualización desde $1",
+    },
+];
+
+fn catalog(lang: &str) -> &'static Catalog {
+    CATALOGS
+        .iter()
+        .find(|c| c.lang == lang)
+        .unwrap_or(&CATALOGS[0])
+}
+
+fn fill(template: &str, args: &[&str]) -> String {
+    let mut s = template.to_owned();
+    for (i, arg) in args.iter().enumerate() {
+        s = s.replace(&format!("${}", i + 1), arg);
+    }
+    s
+}
+
+/// Whether there are messages for a language, rather than falling back to English.
+pub fn known(lang: &str) -> bool {
+    CATALOGS.iter().any(|c| c.lang == lang)
+}
+
+pub fn commits(lang: &str, n: usize) -> String {
+    fill(catalog(lang).commits, &[&n.to_string()])
+}
+
+pub fn others(lang: &str, first: &str, n: usize) -> String {
+    fill(catalog(lang).others, &[first, &n.to_string()])
+}
+
+pub fn update(lang: &str, url: &str) -> String {
+    fill(catalog(lang).update, &[url])
+}
+
+#[test]
+fn test_messages() {
+    assert_eq!(commits("de", 3), "3 Commits");
+    assert_eq!(others("fr", "Alice", 2), "Alice et 2 autres");
+    assert_eq!(
+        update("xx", "https://example.org"),
+        "update from https://example.org"
+    );
+}
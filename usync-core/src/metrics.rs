@@ -0,0 +1,83 @@
+use std::fmt::Write;
+use std::sync::atomic::{AtomicI64, Ordering};
+
+/// Counters and gauges exposed on `/metrics` in the Prometheus text format.
+pub struct Metrics {
+    /// requests left in the current GitHub rate limit window, -1 if unknown
+    pub github_ratelimit_remaining: AtomicI64,
+    /// requests allowed per GitHub rate limit window, -1 if unknown
+    pub github_ratelimit_limit: AtomicI64,
+    /// when the GitHub rate limit window resets, in seconds since the epoch, -1 if unknown
+    pub github_ratelimit_reset: AtomicI64,
+    /// syncs waiting for a free slot
+    pub syncs_queued: AtomicI64,
+    /// syncs holding a slot
+    pub syncs_running: AtomicI64,
+    /// how many syncs may run at once
+    pub sync_limit: AtomicI64,
+    /// syncs that panicked
+    pub sync_panics: AtomicI64,
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self {
+            github_ratelimit_remaining: AtomicI64::new(-1),
+            github_ratelimit_limit: AtomicI64::new(-1),
+            github_ratelimit_reset: AtomicI64::new(-1),
+            syncs_queued: AtomicI64::new(0),
+            syncs_running: AtomicI64::new(0),
+            sync_limit: AtomicI64::new(0),
+            sync_panics: AtomicI64::new(0),
+        }
+    }
+}
+
+impl Metrics {
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        let mut metric = |name: &str, kind: &str, help: &str, value: i64| {
+            let _ = writeln!(out, "# HELP {name} {help}");
+            let _ = writeln!(out, "# TYPE {name} {kind}");
+            let _ = writeln!(out, "{name} {value}");
+        };
+        let mut gauge = |name: &str, help: &str, value: i64| metric(name, "gauge", help, value);
+        gauge(
+            "usync_github_ratelimit_remaining",
+            "Requests left in the current GitHub rate limit window.",
+            self.github_ratelimit_remaining.load(Ordering::Relaxed),
+        );
+        gauge(
+            "usync_github_ratelimit_limit",
+            "Requests allowed per GitHub rate limit window.",
+            self.github_ratelimit_limit.load(Ordering::Relaxed),
+        );
+        gauge(
+            "usync_github_ratelimit_reset_seconds",
+            "When the GitHub rate limit window resets, in seconds since the epoch.",
+            self.github_ratelimit_reset.load(Ordering::Relaxed),
+        );
+        gauge(
+            "usync_syncs_queued",
+            "Syncs waiting for a free slot.",
+            self.syncs_queued.load(Ordering::Relaxed),
+        );
+        gauge(
+            "usync_syncs_running",
+            "Syncs currently running.",
+            self.syncs_running.load(Ordering::Relaxed),
+        );
+        gauge(
+            "usync_sync_limit",
+            "How many syncs may run at once.",
+            self.sync_limit.load(Ordering::Relaxed),
+        );
+        metric(
+            "usync_sync_panics_total",
+            "counter",
+            "Syncs that panicked.",
+            self.sync_panics.load(Ordering::Relaxed),
+        );
+        out
+    }
+}
@@ -0,0 +1,86 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, bail, eyre};
+use tracing::info;
+use tracing::warn;
+
+use crate::github::{branch_head, create_branch, get_contents, put_contents, repo_name};
+use crate::parser::{SyncSource, Target};
+use crate::{SharedState, set_period};
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+}
+
+/// Commit the deployed content of one page to the mirror branch, if it changed.
+async fn mirror_page(ss: &SharedState, source: &SyncSource, target: &Target) -> Result<()> {
+    let repo = repo_name(&source.repo).ok_or_else(|| eyre!("non github URL"))?;
+    let branch = &ss.config().mirror.branch;
+
+    if branch_head(ss, repo, branch).await?.is_none() {
+        // start the mirror off the synced branch, so it diffs cleanly against it
+        let Some(base) = source.ref_.strip_prefix("refs/heads/") else {
+            bail!("can only mirror branches");
+        };
+        let head = branch_head(ss, repo, base)
+            .await?
+            .ok_or_else(|| eyre!("no branch {base}"))?;
+        create_branch(ss, repo, branch, &head).await?;
+    }
+
+    let wikitext = crate::wp::fetch(&ss.client(&source.wiki), &target.title)
+        .await?
+        .slots
+        .main
+        .content;
+    let current = get_contents(ss, repo, &target.path, branch).await?;
+    if let Some(current) = &current
+        && current.decode()? == wikitext.as_bytes()
+    {
+        return Ok(());
+    }
+
+    let message = format!("Mirror {} from {}", target.title, source.wiki);
+    put_contents(
+        ss,
+        repo,
+        &target.path,
+        branch,
+        &message,
+        wikitext.as_bytes(),
+        current.as_ref().map(|c| &*c.sha),
+    )
+    .await?;
+    info!(title = %target.title, %repo, "mirrored");
+    Ok(())
+}
+
+pub async fn task(cx: Context) {
+    let mut int = tokio::time::interval(Duration::from_secs(cx.ss.config().mirror.interval_secs));
+
+    loop {
+        int.tick().await;
+        set_period(&mut int, cx.ss.config().mirror.interval_secs);
+
+        let mappings = cx
+            .ss
+            .map
+            .load()
+            .iter()
+            .flat_map(|(source, targets)| targets.iter().map(|t| (source.clone(), t.clone())))
+            // built artifacts and compiled output don't belong in the tree
+            .filter(|(_, t)| t.artifact.is_none() && t.transform.is_none())
+            .collect::<Vec<_>>();
+
+        for (source, target) in mappings {
+            if let Err(e) = mirror_page(&cx.ss, &source, &target).await {
+                warn!(?e, title = %target.title, "couldn't mirror page");
+            }
+        }
+    }
+}
+
+pub fn start(cx: Context) {
+    tokio::spawn(task(cx));
+}
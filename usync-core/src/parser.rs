@@ -0,0 +1,866 @@
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Receiver;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, timeout_at};
+use tracing::debug;
+use tracing::warn;
+
+use crate::config::{Anchor, Config, Discovery};
+use crate::updater::{Header, parse_header};
+use crate::wp::{MultiPageResponse, Page};
+use crate::{SharedState, set_period};
+
+#[derive(Debug)]
+struct PageInfo {
+    title: String,
+    ns: i64,
+    contentmodel: String,
+    content: String,
+    /// who made the latest revision, and so is vouching for the header
+    editor: Option<String>,
+}
+
+impl PageInfo {
+    /// `None` if there's no revision to go by.
+    fn new(p: Page) -> Option<Self> {
+        let rev = p.revisions.into_iter().next()?;
+        Some(PageInfo {
+            title: p.title,
+            ns: p.ns,
+            contentmodel: rev.slots.main.contentmodel,
+            content: rev.slots.main.content,
+            editor: rev.user,
+        })
+    }
+}
+
+#[derive(Clone, Hash, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct SyncSource {
+    /// name of the wiki in the config
+    pub wiki: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+}
+
+/// A synced page and the file it declares in its header.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize, Debug)]
+pub struct Target {
+    pub title: String,
+    pub path: String,
+    /// the workflow artifact `path` lives in, if it isn't a file in the tree
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub artifact: Option<String>,
+    /// how the file is compiled before deploying, if it is
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub transform: Option<String>,
+    /// directory of `<lang>.json` translations, synced to `<title>/<lang>.json`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub i18n: Option<String>,
+    /// Markdown documentation, synced as wikitext to `<title>/doc`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub doc: Option<String>,
+}
+
+pub type SyncMap = HashMap<SyncSource, Vec<Target>>;
+
+/// Where a page is synced from, if it is.
+pub fn find_mapping(map: &SyncMap, wiki: &str, title: &str) -> Option<(SyncSource, Target)> {
+    map.iter().find_map(|(source, targets)| {
+        let target = targets.iter().find(|t| t.title == title)?;
+        (source.wiki == wiki).then(|| (source.clone(), target.clone()))
+    })
+}
+
+/// Several pages declaring the same file. Only `winner` is kept in the map.
+#[derive(Serialize, Clone, Debug)]
+pub struct Conflict {
+    pub wiki: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+    pub path: String,
+    pub winner: String,
+    pub losers: Vec<String>,
+}
+
+/// A page whose mapping was refused.
+#[derive(Serialize, Clone, Debug)]
+pub struct Rejected {
+    pub wiki: String,
+    pub repo: String,
+    #[serde(rename = "ref")]
+    pub ref_: String,
+    pub title: String,
+    pub reason: String,
+}
+
+/// Problems found while building the map.
+#[derive(Serialize, Clone, Debug, Default)]
+pub struct Report {
+    pub conflicts: Vec<Conflict>,
+    pub rejected: Vec<Rejected>,
+}
+
+impl Report {
+    /// Drop what was reported about a page, before reporting on it afresh.
+    fn forget(&mut self, wiki: &str, title: &str) {
+        self.rejected.retain(|r| r.wiki != wiki || r.title != title);
+        for c in &mut self.conflicts {
+            if c.wiki == wiki {
+                c.losers.retain(|l| l != title);
+            }
+        }
+        self.conflicts
+            .retain(|c| !c.losers.is_empty() && (c.wiki != wiki || c.winner != title));
+    }
+}
+
+/// Build the map from every discovered header, dropping duplicate declarations of one file
+/// and registrations over the quota.
+///
+/// Conflicts and quotas both favor alphabetically earlier titles, so repeated rebuilds agree.
+fn build_map(mut found: Vec<(SyncSource, Target)>, limits: &Discovery) -> (SyncMap, Report) {
+    found.sort_by(|a, b| (&a.0.wiki, &a.1.title).cmp(&(&b.0.wiki, &b.1.title)));
+    found.dedup_by(|a, b| a.0.wiki == b.0.wiki && a.1.title == b.1.title);
+
+    let mut owners: HashMap<(SyncSource, String, Option<String>), Vec<Target>> = HashMap::new();
+    for (source, target) in found {
+        owners
+            .entry((source, target.path.clone(), target.artifact.clone()))
+            .or_default()
+            .push(target);
+    }
+
+    let mut report = Report::default();
+    let mut winners = Vec::new();
+    for ((source, path, _), mut targets) in owners {
+        let winner = targets.remove(0);
+        if !targets.is_empty() {
+            let losers = targets.into_iter().map(|t| t.title).collect::<Vec<_>>();
+            warn!(?source, %path, winner = %winner.title, ?losers, "conflicting mappings");
+            report.conflicts.push(Conflict {
+                wiki: source.wiki.clone(),
+                repo: source.repo.clone(),
+                ref_: source.ref_.clone(),
+                path,
+                winner: winner.title.clone(),
+                losers,
+            });
+        }
+        winners.push((source, winner));
+    }
+    winners.sort_by(|a, b| a.1.title.cmp(&b.1.title));
+
+    let mut syncs: SyncMap = HashMap::new();
+    let mut per_repo: HashMap<String, usize> = HashMap::new();
+    for (source, target) in winners {
+        let repo_count = per_repo.entry(source.repo.clone()).or_default();
+        let targets = syncs.entry(source.clone()).or_default();
+        if let Some(reason) = refusal(&source.repo, targets.len(), *repo_count, limits) {
+            warn!(?source, title = %target.title, reason, "refusing registration");
+            report.rejected.push(Rejected {
+                wiki: source.wiki,
+                repo: source.repo,
+                ref_: source.ref_,
+                title: target.title,
+                reason: reason.to_owned(),
+            });
+            continue;
+        }
+        *repo_count += 1;
+        targets.push(target);
+    }
+    syncs.retain(|_, targets| !targets.is_empty());
+
+    (syncs, report)
+}
+
+/// Check the repository lists and registration caps, given how many titles are already
+/// registered.
+fn refusal(
+    repo: &str,
+    source_count: usize,
+    repo_count: usize,
+    limits: &Discovery,
+) -> Option<&'static str> {
+    if !limits.allows_repo(repo) {
+        Some("repository is not allowed to sync")
+    } else if source_count >= limits.max_titles_per_source {
+        Some("too many titles for this repository and ref")
+    } else if repo_count >= limits.max_titles_per_repo {
+        Some("too many titles for this repository")
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+fn found(path: &str, title: &str) -> (SyncSource, Target) {
+    (
+        SyncSource {
+            wiki: "enwiki".to_owned(),
+            repo: "https://github.com/fee1-dead/usync".to_owned(),
+            ref_: "refs/heads/main".to_owned(),
+        },
+        Target {
+            title: title.to_owned(),
+            path: path.to_owned(),
+            artifact: None,
+            transform: None,
+            i18n: None,
+            doc: None,
+        },
+    )
+}
+
+#[test]
+fn test_required_right() {
+    assert_eq!(
+        required_right(2, "User:Alice/a.js", "javascript", "Alice"),
+        None
+    );
+    assert_eq!(
+        required_right(2, "User:Alice/a.js", "javascript", "Bob"),
+        Some("edituserjs")
+    );
+    assert_eq!(
+        required_right(8, "MediaWiki:Gadget-a.css", "css", "Alice"),
+        Some("editsitecss")
+    );
+    assert_eq!(required_right(828, "Module:A", "Scribunto", "Bob"), None);
+}
+
+#[test]
+fn test_parse_approvals() {
+    let text = "Approved mappings:\n\
+        * https://github.com/fee1-dead/usync → User:A/a.js\n\
+        *https://github.com/fee1-dead/usync->MediaWiki:Gadget-b_c.js\n\
+        * not a mapping\n";
+    assert_eq!(
+        parse_approvals(text),
+        [
+            (
+                "https://github.com/fee1-dead/usync".to_owned(),
+                "User:A/a.js".to_owned()
+            ),
+            (
+                "https://github.com/fee1-dead/usync".to_owned(),
+                "MediaWiki:Gadget-b c.js".to_owned()
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_build_map_conflicts() {
+    let (map, report) = build_map(
+        vec![
+            found("a.js", "User:B/a.js"),
+            found("a.js", "User:A/a.js"),
+            found("b.js", "User:A/b.js"),
+            found("b.js", "User:A/b.js"),
+        ],
+        &Discovery::default(),
+    );
+
+    let mut titles = map
+        .into_values()
+        .next()
+        .unwrap()
+        .into_iter()
+        .map(|t| t.title)
+        .collect::<Vec<_>>();
+    titles.sort();
+    assert_eq!(titles, ["User:A/a.js", "User:A/b.js"]);
+    assert_eq!(report.conflicts.len(), 1);
+    assert_eq!(report.conflicts[0].winner, "User:A/a.js");
+    assert_eq!(report.conflicts[0].losers, ["User:B/a.js"]);
+}
+
+#[test]
+fn test_build_map_quota() {
+    let limits = Discovery {
+        max_titles_per_source: 2,
+        ..Discovery::default()
+    };
+    let (map, report) = build_map(
+        vec![
+            found("c.js", "User:A/c.js"),
+            found("b.js", "User:A/b.js"),
+            found("a.js", "User:A/a.js"),
+        ],
+        &limits,
+    );
+
+    assert_eq!(map.values().map(Vec::len).sum::<usize>(), 2);
+    assert_eq!(report.rejected.len(), 1);
+    assert_eq!(report.rejected[0].title, "User:A/c.js");
+}
+
+#[test]
+fn test_report_forget() {
+    let limits = Discovery {
+        max_titles_per_source: 1,
+        ..Discovery::default()
+    };
+    let (_, mut report) = build_map(
+        vec![
+            found("a.js", "User:A/a.js"),
+            found("a.js", "User:B/a.js"),
+            found("a.js", "User:C/a.js"),
+            found("b.js", "User:A/b.js"),
+        ],
+        &limits,
+    );
+    assert_eq!(report.rejected.len(), 1);
+
+    report.forget("enwiki", "User:B/a.js");
+    report.forget("enwiki", "User:A/b.js");
+    assert!(report.rejected.is_empty());
+    assert_eq!(report.conflicts[0].losers, ["User:C/a.js"]);
+
+    report.forget("enwiki", "User:A/a.js");
+    assert!(report.conflicts.is_empty());
+}
+
+/// Find every sync page on one wiki.
+async fn search(
+    ss: &SharedState,
+    wiki: &str,
+) -> color_eyre::Result<(Vec<(SyncSource, Target)>, Vec<Rejected>)> {
+    let config = &ss.config().wikis[wiki];
+    let (generator, anchor) = match (&config.category, &config.anchor) {
+        (Some(category), _) => ("categorymembers", ("gcmtitle", category.clone())),
+        (None, Anchor::PageId(id)) => ("transcludedin", ("pageids", id.to_string())),
+        (None, Anchor::Title(title)) => ("transcludedin", ("titles", title.clone())),
+    };
+    let client = ss.client(wiki);
+    let mut stream = client.get_all(
+        [
+            ("action", "query"),
+            ("generator", generator),
+            (anchor.0, &anchor.1),
+            ("prop", "revisions"),
+            ("rvprop", "content|contentmodel|user"),
+            ("rvslots", "main"),
+        ],
+        |r: MultiPageResponse| {
+            Ok(r.query
+                .pages
+                .into_iter()
+                .filter_map(PageInfo::new)
+                .collect::<Vec<_>>())
+        },
+    );
+
+    let mut found = Vec::new();
+    let mut rejected = Vec::new();
+    let mut rights = HashMap::new();
+    let approvals = approvals(ss, wiki).await?;
+    load_usernames(ss, wiki).await;
+
+    while let Some(item) = stream.next().await {
+        let item = item?;
+
+        if !ss.config().discovery.accepts(&item.contentmodel) {
+            continue;
+        }
+
+        let Some(header) = parse_header(&item.contentmodel, &item.content) else {
+            continue;
+        };
+        let checked = check_page(ss, wiki, &item, &header, &mut rights, approvals.as_ref());
+        if let Err(reason) = checked.await {
+            warn!(%wiki, title = %item.title, %reason, "refusing registration");
+            rejected.push(Rejected {
+                wiki: wiki.to_owned(),
+                repo: header.repo,
+                ref_: header.ref_,
+                title: item.title,
+                reason,
+            });
+            continue;
+        }
+        match check_header(ss, wiki, header, item.title) {
+            Ok(page) => found.push(page),
+            Err(r) => rejected.push(r),
+        }
+    }
+
+    Ok((found, rejected))
+}
+
+/// The right needed on top of editing a page to have it synced, so a mapping can't make the
+/// bot edit what the person who set it up couldn't.
+fn required_right(ns: i64, title: &str, contentmodel: &str, editor: &str) -> Option<&'static str> {
+    match ns {
+        // User:, anyone may have their own scripts synced
+        2 => {
+            let owner = title.split_once(':')?.1.split('/').next()?;
+            if owner == editor {
+                return None;
+            }
+            match contentmodel {
+                "javascript" => Some("edituserjs"),
+                "css" => Some("editusercss"),
+                "json" => Some("edituserjson"),
+                _ => None,
+            }
+        }
+        // MediaWiki:
+        8 => Some(match contentmodel {
+            "javascript" => "editsitejs",
+            "css" => "editsitecss",
+            "json" => "editsitejson",
+            _ => "editinterface",
+        }),
+        _ => None,
+    }
+}
+
+/// Check that whoever last edited a sync page may edit it through the bot. `rights` caches
+/// the rights of users looked up before.
+async fn check_editor(
+    ss: &SharedState,
+    wiki: &str,
+    page: &PageInfo,
+    rights: &mut HashMap<String, Vec<String>>,
+) -> Result<(), String> {
+    let Some(editor) = &page.editor else {
+        return Err("the latest editor is hidden".to_owned());
+    };
+    // our own edits only ever keep the header that was already there
+    if ss.users.get(wiki) == Some(editor) {
+        return Ok(());
+    }
+    let Some(right) = required_right(page.ns, &page.title, &page.contentmodel, editor) else {
+        return Ok(());
+    };
+
+    if !rights.contains_key(editor) {
+        let r = crate::wp::user_rights(&ss.client(wiki), editor)
+            .await
+            .map_err(|e| format!("couldn't look up the rights of {editor}: {e}"))?;
+        rights.insert(editor.clone(), r);
+    }
+    if rights[editor].iter().any(|r| r == right) {
+        Ok(())
+    } else {
+        Err(format!("{editor} doesn't have the {right} right"))
+    }
+}
+
+/// Mappings approved on the wiki, as pairs of a canonical repository URL and a title.
+type Approvals = HashSet<(String, String)>;
+
+/// Parse the `* repo → title` lines of an approval page.
+fn parse_approvals(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .filter_map(|line| {
+            let line = line.trim().strip_prefix('*')?;
+            let (repo, title) = line.split_once('→').or_else(|| line.split_once("->"))?;
+            let (repo, title) = (repo.trim(), title.trim());
+            // titles are compared as the wiki lists them, with spaces
+            let title = title.replace('_', " ");
+            (!repo.is_empty() && !title.is_empty()).then(|| (repo.to_owned(), title))
+        })
+        .collect()
+}
+
+/// The approved mappings of a wiki, or `None` if it doesn't require approval.
+async fn approvals(ss: &SharedState, wiki: &str) -> color_eyre::Result<Option<Approvals>> {
+    let Some(page) = ss.config().wikis[wiki].approval_page.clone() else {
+        return Ok(None);
+    };
+    // no page means nothing is approved yet
+    let text = crate::wp::content(&ss.client(wiki), &page)
+        .await?
+        .unwrap_or_default();
+    Ok(Some(
+        parse_approvals(&text)
+            .into_iter()
+            .map(|(repo, title)| (ss.canonical_repo(&repo), title))
+            .collect(),
+    ))
+}
+
+/// Refresh the wiki usernames of GitHub accounts from a wiki's usernames page, keeping the
+/// ones we had if it can't be read.
+async fn load_usernames(ss: &SharedState, wiki: &str) {
+    let Some(page) = ss.config().wikis[wiki].usernames_page.clone() else {
+        return;
+    };
+    let usernames = match crate::wp::content(&ss.client(wiki), &page).await {
+        Ok(text) => serde_json::from_str(&text.unwrap_or_else(|| "{}".to_owned())),
+        Err(e) => {
+            warn!(?e, %wiki, %page, "couldn't fetch usernames page");
+            return;
+        }
+    };
+    match usernames {
+        Ok(usernames) => {
+            ss.usernames
+                .lock()
+                .unwrap()
+                .insert(wiki.to_owned(), usernames);
+        }
+        Err(e) => warn!(?e, %wiki, %page, "usernames page isn't a JSON object of strings"),
+    }
+}
+
+/// Check a sync page against its editor's rights and the approval list, if there is one.
+async fn check_page(
+    ss: &SharedState,
+    wiki: &str,
+    page: &PageInfo,
+    header: &Header,
+    rights: &mut HashMap<String, Vec<String>>,
+    approvals: Option<&Approvals>,
+) -> Result<(), String> {
+    check_editor(ss, wiki, page, rights).await?;
+    if let Some(approvals) = approvals {
+        let key = (ss.canonical_repo(&header.repo), page.title.clone());
+        if !approvals.contains(&key) {
+            return Err("the mapping isn't approved".to_owned());
+        }
+    }
+    Ok(())
+}
+
+/// Validate a discovered header before it goes into the map.
+fn check_header(
+    ss: &SharedState,
+    wiki: &str,
+    header: Header,
+    title: String,
+) -> Result<(SyncSource, Target), Rejected> {
+    if let Err(reason) = ss.config().check_wiki(&header.wikis, wiki) {
+        warn!(%wiki, %title, %reason, "refusing registration");
+        return Err(Rejected {
+            wiki: wiki.to_owned(),
+            repo: header.repo,
+            ref_: header.ref_,
+            title,
+            reason,
+        });
+    }
+
+    let mut header = header;
+    header.repo = ss.canonical_repo(&header.repo);
+    Ok(found_page(wiki, header, title))
+}
+
+fn found_page(wiki: &str, header: Header, title: String) -> (SyncSource, Target) {
+    (
+        SyncSource {
+            wiki: wiki.to_owned(),
+            repo: header.repo,
+            ref_: header.ref_,
+        },
+        Target {
+            title,
+            path: header.path,
+            artifact: header.artifact,
+            transform: header.transform,
+            i18n: header.i18n,
+            doc: header.doc,
+        },
+    )
+}
+
+/// Rebuild the map from every configured wiki.
+async fn search_all(ss: &SharedState) -> color_eyre::Result<(SyncMap, Report)> {
+    let mut found = Vec::new();
+    let mut rejected = Vec::new();
+    for wiki in ss.config().wikis.keys() {
+        let (f, r) = search(ss, wiki).await?;
+        found.extend(f);
+        rejected.extend(r);
+    }
+
+    let (map, mut report) = build_map(found, &ss.config().discovery);
+    report.rejected.extend(rejected);
+    Ok((map, report))
+}
+
+#[derive(Serialize, Deserialize)]
+struct SnapshotEntry {
+    #[serde(flatten)]
+    source: SyncSource,
+    titles: Vec<Target>,
+}
+
+fn snapshot_path(config: &Config) -> PathBuf {
+    config.state_dir.join("sync_map.json")
+}
+
+/// Load the map saved by a previous run, so pushes can be handled before the first rebuild.
+pub fn load_map(config: &Config) -> SyncMap {
+    let path = snapshot_path(config);
+    let entries = match fs::read_to_string(&path) {
+        Ok(s) => serde_json::from_str::<Vec<SnapshotEntry>>(&s),
+        Err(e) => {
+            debug!(?e, ?path, "no map snapshot");
+            return HashMap::new();
+        }
+    };
+
+    match entries {
+        Ok(entries) => entries.into_iter().map(|e| (e.source, e.titles)).collect(),
+        Err(e) => {
+            warn!(?e, ?path, "couldn't parse map snapshot");
+            HashMap::new()
+        }
+    }
+}
+
+fn save_map(ss: &SharedState) -> color_eyre::Result<()> {
+    let entries = ss
+        .map
+        .load()
+        .iter()
+        .map(|(source, titles)| SnapshotEntry {
+            source: source.clone(),
+            titles: titles.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    // write to a temporary file first so a crash can't leave a truncated snapshot
+    let path = snapshot_path(&ss.config());
+    let tmp = path.with_extension("json.tmp");
+    fs::write(&tmp, serde_json::to_vec(&entries)?)?;
+    fs::rename(tmp, path)?;
+    Ok(())
+}
+
+/// A request to refresh the sync map.
+#[derive(Debug)]
+pub enum Reparse {
+    /// rebuild the whole map from scratch
+    All,
+    /// re-parse a single page and merge it into the existing map
+    Title { wiki: String, title: String },
+}
+
+/// Re-parse a single page and update its entry in the map.
+async fn update_title(ss: &SharedState, wiki: &str, title: &str) -> color_eyre::Result<()> {
+    let page = crate::wp::fetch_page(&ss.client(wiki), title).await?;
+    // a page renamed or turned into a redirect is mapped under the title it goes by now
+    let canonical = page.title.clone();
+    // a deleted page has no header left, which drops it from the map
+    let page = PageInfo::new(page);
+    let mut header = match &page {
+        Some(page) if ss.config().discovery.accepts(&page.contentmodel) => {
+            parse_header(&page.contentmodel, &page.content)
+        }
+        _ => None,
+    };
+    {
+        let mut report = ss.report.lock().unwrap();
+        report.forget(wiki, title);
+        report.forget(wiki, &canonical);
+    }
+    if let (Some(page), Some(h)) = (&page, &header) {
+        // only pages that still sync anything need approving
+        let approvals = approvals(ss, wiki).await?;
+        if let Err(reason) =
+            check_page(ss, wiki, page, h, &mut HashMap::new(), approvals.as_ref()).await
+        {
+            warn!(%wiki, title = %canonical, %reason, "refusing registration");
+            ss.report.lock().unwrap().rejected.push(Rejected {
+                wiki: wiki.to_owned(),
+                repo: h.repo.clone(),
+                ref_: h.ref_.clone(),
+                title: canonical.clone(),
+                reason,
+            });
+            header = None;
+        }
+    }
+
+    // the parser task is the only writer, so nothing can change the map in between
+    let mut map = SyncMap::clone(&ss.map.load());
+    if canonical != title {
+        merge_title(ss, &mut map, wiki, title, None);
+    }
+    merge_title(ss, &mut map, wiki, &canonical, header);
+    ss.map.store(Arc::new(map));
+    Ok(())
+}
+
+/// Replace whatever `title` was mapped to with what its header says now.
+fn merge_title(
+    ss: &SharedState,
+    map: &mut SyncMap,
+    wiki: &str,
+    title: &str,
+    header: Option<Header>,
+) {
+    for (source, targets) in map.iter_mut() {
+        if source.wiki == wiki {
+            targets.retain(|t| t.title != title);
+        }
+    }
+    map.retain(|_, targets| !targets.is_empty());
+
+    let Some(header) = header else {
+        return;
+    };
+
+    let (source, target) = match check_header(ss, wiki, header, title.to_owned()) {
+        Ok(page) => page,
+        Err(r) => {
+            ss.report.lock().unwrap().rejected.push(r);
+            return;
+        }
+    };
+    let targets = map.entry(source.clone()).or_default();
+
+    // same rule as `build_map`: the alphabetically first title wins
+    match targets
+        .iter_mut()
+        .find(|t| t.path == target.path && t.artifact == target.artifact)
+    {
+        Some(existing) => {
+            let (winner, loser) = if existing.title.as_str() < title {
+                (existing.title.clone(), title.to_owned())
+            } else {
+                (
+                    title.to_owned(),
+                    std::mem::replace(&mut existing.title, title.to_owned()),
+                )
+            };
+            warn!(?source, path = %target.path, %winner, %loser, "conflicting mappings");
+            ss.report.lock().unwrap().conflicts.push(Conflict {
+                wiki: source.wiki,
+                repo: source.repo,
+                ref_: source.ref_,
+                path: target.path,
+                winner,
+                losers: vec![loser],
+            });
+        }
+        None => {
+            let source_count = targets.len();
+            let repo_count = map
+                .iter()
+                .filter(|(s, _)| s.repo == source.repo)
+                .map(|(_, targets)| targets.len())
+                .sum();
+            let discovery = &ss.config().discovery;
+            if let Some(reason) = refusal(&source.repo, source_count, repo_count, discovery) {
+                warn!(?source, %title, reason, "refusing registration");
+                ss.report.lock().unwrap().rejected.push(Rejected {
+                    wiki: source.wiki,
+                    repo: source.repo,
+                    ref_: source.ref_,
+                    title: target.title,
+                    reason: reason.to_owned(),
+                });
+            } else {
+                map.entry(source).or_default().push(target);
+            }
+        }
+    }
+    map.retain(|_, targets| !targets.is_empty());
+}
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+    pub reparse_recv: Receiver<Reparse>,
+}
+
+pub async fn task(mut ctx: Context) {
+    // passively update everything per hour
+    let mut int = tokio::time::interval(Duration::from_secs(
+        ctx.ss.config().discovery.rebuild_interval_secs,
+    ));
+
+    let mut last_rebuild: Option<Instant> = None;
+
+    // all changes to the map go through here, so targeted updates can't race with a rebuild
+    loop {
+        let req = tokio::select! {
+            _ = int.tick() => {
+                set_period(&mut int, ctx.ss.config().discovery.rebuild_interval_secs);
+                Reparse::All
+            }
+            Some(req) = ctx.reparse_recv.recv() => req,
+            _ = ctx.ss.edited_notify.notified() => {
+                let edited = std::mem::take(&mut *ctx.ss.edited.lock().unwrap());
+                for (wiki, title) in edited {
+                    debug!(%wiki, %title, "reparsing edited page");
+                    if let Err(e) = update_title(&ctx.ss, &wiki, &title).await {
+                        debug!(?e, %wiki, %title, "couldn't reparse page");
+                    }
+                }
+                if let Err(e) = save_map(&ctx.ss) {
+                    warn!(?e, "couldn't save map snapshot");
+                }
+                continue;
+            }
+            else => break,
+        };
+
+        let debounce = Duration::from_secs(ctx.ss.config().discovery.debounce_secs);
+        let min_interval = Duration::from_secs(ctx.ss.config().discovery.min_interval_secs);
+        match req {
+            Reparse::All => {
+                // coalesce a burst of requests into a single rebuild, and hold it off until
+                // the last one is at least `min_interval` old
+                let mut deadline = Instant::now() + debounce;
+                if let Some(ready) = last_rebuild.map(|t| t + min_interval)
+                    && ready > deadline
+                {
+                    debug!("map was rebuilt recently, waiting");
+                    deadline = ready;
+                }
+                let mut titles = Vec::new();
+                while let Ok(Some(req)) = timeout_at(deadline, ctx.reparse_recv.recv()).await {
+                    if let Reparse::Title { wiki, title } = req {
+                        titles.push((wiki, title));
+                    }
+                }
+
+                if let Ok((res, report)) = search_all(&ctx.ss).await {
+                    debug!(?res, "parsed map");
+                    ctx.ss.map.store(Arc::new(res));
+                    *ctx.ss.report.lock().unwrap() = report;
+                    *ctx.ss.rebuilt_at.lock().unwrap() = Some(Instant::now());
+                    last_rebuild = Some(Instant::now());
+
+                    // wake up pushes waiting for a fresh map
+                    ctx.ss.rebuilt.send_replace(());
+                }
+
+                for (wiki, title) in titles {
+                    if let Err(e) = update_title(&ctx.ss, &wiki, &title).await {
+                        debug!(?e, %wiki, %title, "couldn't reparse page");
+                    }
+                }
+            }
+            Reparse::Title { wiki, title } => {
+                debug!(%wiki, %title, "reparsing page");
+                if let Err(e) = update_title(&ctx.ss, &wiki, &title).await {
+                    debug!(?e, %wiki, %title, "couldn't reparse page");
+                }
+            }
+        }
+
+        if let Err(e) = save_map(&ctx.ss) {
+            warn!(?e, "couldn't save map snapshot");
+        }
+    }
+}
+
+pub fn start(ctx: Context) -> JoinHandle<()> {
+    tokio::spawn(task(ctx))
+}
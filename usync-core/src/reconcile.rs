@@ -0,0 +1,178 @@
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+use tokio::sync::mpsc::Sender;
+use tracing::{info, warn};
+
+use crate::github::{branch_head, last_change, repo_name};
+use crate::{
+    GitHubAuthor, GitHubCommit, GitHubPush, Repository, SharedState, new_sync_id, set_period,
+    skips_sync,
+};
+
+/// What reconciliation does with a page that differs from its branch.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Drift {
+    Fix,
+    Report,
+}
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+    pub send: Sender<GitHubPush>,
+}
+
+/// A push bringing every mapped file of a branch up to its current head.
+fn catch_up(
+    repo_url: &str,
+    repo: &str,
+    ref_: &str,
+    head: String,
+    paths: Vec<String>,
+    drift: Option<Drift>,
+) -> GitHubPush {
+    let usync = GitHubAuthor {
+        name: "usync".to_owned(),
+        username: None,
+    };
+    GitHubPush {
+        sync_id: new_sync_id(),
+        compare: format!("{repo_url}/commit/{head}"),
+        commits: vec![GitHubCommit {
+            author: usync.clone(),
+            committer: usync,
+            message: "catch up with missed pushes".to_owned(),
+            added: Vec::new(),
+            modified: Vec::new(),
+            removed: Vec::new(),
+            url: String::new(),
+        }],
+        ref_: ref_.to_owned(),
+        before: String::new(),
+        after: head,
+        forced: false,
+        head_commit: None,
+        repository: Repository {
+            html_url: repo_url.to_owned(),
+            contents_url: format!("https://api.github.com/repos/{repo}/contents/{{+path}}"),
+        },
+        // the map was just built, there's nothing to rebuild for
+        retry: true,
+        changed: Some(paths),
+        run: None,
+        drift,
+        confirmed: false,
+        dry_run: false,
+        sender: None,
+    }
+}
+
+/// Queue a sync of every mapped file at its branch head. Pages already matching are left
+/// alone by the updater, so only drifted ones get edited, or reported if configured so.
+async fn reconcile(cx: &Context) {
+    let drift = if cx.ss.config().reconcile_report_only {
+        Drift::Report
+    } else {
+        Drift::Fix
+    };
+    let mut branches: BTreeMap<(String, String), Vec<String>> = BTreeMap::new();
+    for (source, targets) in cx.ss.map.load().iter() {
+        branches
+            .entry((source.repo.clone(), source.ref_.clone()))
+            .or_default()
+            .extend(
+                targets
+                    .iter()
+                    .filter(|t| t.artifact.is_none())
+                    .map(|t| t.path.clone()),
+            );
+    }
+
+    info!(branches = branches.len(), "reconciling");
+    for ((repo, ref_), paths) in branches {
+        let Some(mut push) = branch_push(&cx.ss, &repo, &ref_, paths, Some(drift)).await else {
+            continue;
+        };
+        // a change pushed with `[skip sync]` stays off the wiki until a later commit changes
+        // the file again
+        let mut paths = Vec::new();
+        for path in push.changed.take().unwrap_or_default() {
+            if !skipped(&cx.ss, &push, &path).await {
+                paths.push(path);
+            }
+        }
+        if paths.is_empty() {
+            continue;
+        }
+        push.changed = Some(paths);
+        if cx.send.send(push).await.is_err() {
+            return;
+        }
+    }
+}
+
+/// Whether the last change to a file asked not to be deployed. Files we can't tell about are
+/// left alone too, until the next reconciliation.
+async fn skipped(ss: &SharedState, push: &GitHubPush, path: &str) -> bool {
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return true;
+    };
+    match last_change(ss, repo, &push.after, path).await {
+        Ok(message) => message.is_some_and(|m| skips_sync(&m)),
+        Err(e) => {
+            warn!(?e, %repo, %path, "couldn't get the file's last change");
+            true
+        }
+    }
+}
+
+/// A push of `paths` at the current head of a mapped branch.
+pub async fn branch_push(
+    ss: &SharedState,
+    repo_url: &str,
+    ref_: &str,
+    paths: Vec<String>,
+    drift: Option<Drift>,
+) -> Option<GitHubPush> {
+    let repo = repo_name(repo_url)?;
+    let branch = ref_.strip_prefix("refs/heads/")?;
+    let head = match branch_head(ss, repo, branch).await {
+        Ok(Some(head)) => head,
+        Ok(None) => {
+            warn!(%repo, %branch, "mapped branch doesn't exist");
+            return None;
+        }
+        Err(e) => {
+            warn!(?e, %repo, %branch, "couldn't get branch head");
+            return None;
+        }
+    };
+    Some(catch_up(repo_url, repo, ref_, head, paths, drift))
+}
+
+pub async fn task(cx: Context) {
+    // the snapshot may be stale, start from a freshly built map
+    let mut rebuilt = cx.ss.rebuilt.subscribe();
+    let _ = rebuilt.changed().await;
+    reconcile(&cx).await;
+
+    let secs = cx.ss.config().reconcile_interval_secs;
+    if secs == 0 {
+        return;
+    }
+    let mut int = tokio::time::interval(Duration::from_secs(secs));
+    // the first tick completes immediately, and we just reconciled
+    int.tick().await;
+    loop {
+        int.tick().await;
+        set_period(&mut int, cx.ss.config().reconcile_interval_secs);
+        reconcile(&cx).await;
+    }
+}
+
+pub fn start(cx: Context) {
+    tokio::spawn(task(cx));
+}
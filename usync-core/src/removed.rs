@@ -0,0 +1,181 @@
+//! Pages whose file was removed from the repository they're synced from.
+
+use serde_json::{Map, Value};
+use tracing::{error, info, warn};
+
+use crate::config::OnRemoved;
+use crate::github::repo_name;
+use crate::parser::{Reparse, Target};
+use crate::updater::{Outcome, header_gates, parse_header};
+use crate::{GitHubPush, SharedState, wp};
+
+/// What a page says in place of its header once its file is gone.
+fn notice(repo: &str, path: &str) -> String {
+    format!(
+        "This page was synced from {path} in {repo}, which has since been removed. It is no longer updated."
+    )
+}
+
+/// A single line comment for a content model, or `None` if it has no comments.
+fn comment(contentmodel: &str, text: &str) -> Option<String> {
+    match contentmodel {
+        "javascript" => Some(format!("// {text}")),
+        "css" | "sanitized-css" => Some(format!("/* {text} */")),
+        "Scribunto" => Some(format!("-- {text}")),
+        _ => None,
+    }
+}
+
+/// `content` with its header taken off, and replaced with `notice` if there is one. With `blank`,
+/// nothing but the notice is kept. `None` for content models we don't sync.
+pub fn unsync(
+    contentmodel: &str,
+    content: &str,
+    notice: Option<&str>,
+    blank: bool,
+) -> Option<String> {
+    if contentmodel == "json" {
+        let mut manifest = match blank {
+            true => Map::new(),
+            false => serde_json::from_str::<Map<String, Value>>(content).ok()?,
+        };
+        match notice {
+            Some(notice) => manifest.insert("_usync".to_owned(), notice.into()),
+            None => manifest.remove("_usync"),
+        };
+        return serde_json::to_string_pretty(&manifest).ok();
+    }
+
+    // the other models we sync keep the header on the first line
+    comment(contentmodel, "")?;
+    let rest = match blank {
+        true => "",
+        false => content.split_once('\n').map_or("", |(_, rest)| rest),
+    };
+    Some(match notice {
+        Some(notice) => format!("{}\n{rest}", comment(contentmodel, notice)?),
+        None => rest.to_owned(),
+    })
+}
+
+#[test]
+fn test_unsync() {
+    let js = "// {{Wikipedia:USync |repo=r |ref=refs/heads/main |path=a.js}}\nfoo();\n";
+    assert_eq!(
+        unsync("javascript", js, Some("gone"), false).unwrap(),
+        "// gone\nfoo();\n"
+    );
+    assert_eq!(unsync("javascript", js, None, false).unwrap(), "foo();\n");
+    assert_eq!(
+        unsync("javascript", js, Some("gone"), true).unwrap(),
+        "// gone\n"
+    );
+    assert_eq!(
+        unsync("css", "/* header */\na {}", Some("gone"), false).unwrap(),
+        "/* gone */\na {}"
+    );
+
+    let json =
+        r#"{"_usync": "{{Wikipedia:USync |repo=r |ref=refs/heads/main |path=a.json}}", "a": 1}"#;
+    assert_eq!(
+        unsync("json", json, None, false).unwrap(),
+        "{\n  \"a\": 1\n}"
+    );
+    assert_eq!(
+        unsync("json", json, Some("gone"), true).unwrap(),
+        "{\n  \"_usync\": \"gone\"\n}"
+    );
+
+    assert_eq!(unsync("wikitext", "foo", Some("gone"), false), None);
+}
+
+/// Take a page off the map now that the push removed its file, doing with it what the wiki's
+/// `on_removed` says.
+pub async fn removed(ss: &SharedState, push: &GitHubPush, wiki: &str, target: &Target) -> Outcome {
+    let title = &target.title;
+    let client = ss.client(wiki);
+    let revision = match wp::fetch(&client, title).await {
+        Ok(revision) => revision,
+        Err(e) => {
+            error!(?e, "couldn't fetch");
+            return Outcome::Failed("couldn't fetch the wiki page".into());
+        }
+    };
+    let wp::MainSlot {
+        content,
+        contentmodel,
+    } = &revision.slots.main;
+    // the page may have moved on to another source since the map was built
+    let Some(header) = parse_header(contentmodel, content) else {
+        return Outcome::Done;
+    };
+    if ss.canonical_repo(&header.repo) != push.repository.html_url || header.path != target.path {
+        return Outcome::Done;
+    }
+    // removing the file shouldn't get around what the header asks of deployments
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return Outcome::Done;
+    };
+    if let Err(outcome) = header_gates(ss, push, repo, &header).await {
+        return outcome;
+    }
+
+    let config = ss.config();
+    let wiki_config = &config.wikis[wiki];
+    let on_removed = wiki_config.on_removed;
+    let notice = notice(&push.repository.html_url, &target.path);
+    let text = match on_removed {
+        OnRemoved::Notify => unsync(contentmodel, content, None, false),
+        OnRemoved::Banner => unsync(contentmodel, content, Some(&notice), false),
+        OnRemoved::Blank => unsync(contentmodel, content, Some(&notice), true),
+    };
+    let Some(text) = text else {
+        return Outcome::Done;
+    };
+    if push.dry_run {
+        info!(?on_removed, "dry run, would take the page off the map");
+        return Outcome::Done;
+    }
+    // there's no copy of the page to take the header off of
+    if config.staging.redirect(wiki, title).is_some() {
+        info!(
+            ?on_removed,
+            "staging, leaving the removed file's page alone"
+        );
+        return Outcome::Done;
+    }
+
+    info!(?on_removed, "file removed, taking the page off the map");
+    let summary = format!(
+        "{} {} was removed from {}",
+        wiki_config.summary_prefix, target.path, push.repository.html_url
+    );
+    // an edit made since we looked shouldn't be overwritten
+    match wp::save_over(&client, title, &text, &summary, &revision).await {
+        Ok(wp::EditResponse { error: None, .. }) => {}
+        Ok(wp::EditResponse { error: Some(e), .. }) => {
+            error!(?e, "edit");
+            return Outcome::Failed(format!("edit failed: {}", e.info));
+        }
+        Err(e) => {
+            error!(?e, "edit");
+            return Outcome::Failed("couldn't take the header off the page".into());
+        }
+    }
+    let reparse = Reparse::Title {
+        wiki: wiki.to_owned(),
+        title: title.clone(),
+    };
+    let _ = ss.reparse.send(reparse).await;
+
+    if on_removed == OnRemoved::Notify {
+        let talk = wp::talk_page(title);
+        let text = format!(
+            "{notice} Its sync header has been taken off; the page can be deleted or maintained by hand. ~~~~"
+        );
+        if let Err(e) = wp::new_section(&client, &talk, "Sync source file removed", &text).await {
+            warn!(?e, %talk, "couldn't post removal notice");
+        }
+    }
+    Outcome::Removed
+}
@@ -0,0 +1,64 @@
+use std::collections::HashSet;
+use std::time::Duration;
+
+use tokio::sync::mpsc::Sender;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use crate::SharedState;
+use crate::parser::Reparse;
+
+/// A repository that moved, whose old URL is still accepted until `until`.
+pub struct Renamed {
+    pub to: String,
+    pub until: Instant,
+}
+
+/// Follow a renamed or transferred repository: keep syncing pages that still name the old URL
+/// for a while, and ask their maintainers to update the headers.
+pub async fn renamed(ss: &SharedState, reparse: &Sender<Reparse>, from: String, to: String) {
+    info!(%from, %to, "repository moved");
+    let grace = Duration::from_secs(ss.config().rename_grace_days * 24 * 60 * 60);
+    ss.renames.lock().unwrap().insert(
+        from.clone(),
+        Renamed {
+            to: to.clone(),
+            until: Instant::now() + grace,
+        },
+    );
+
+    let affected = ss
+        .map
+        .load()
+        .iter()
+        .filter(|(source, _)| source.repo == from)
+        .flat_map(|(source, targets)| {
+            targets
+                .iter()
+                .map(|t| (source.wiki.clone(), t.title.clone()))
+        })
+        .collect::<HashSet<_>>();
+
+    // pick up the new URL in the map
+    let _ = reparse.send(Reparse::All).await;
+
+    let text = format!(
+        "The repository this page is synced from has moved from {from} to {to}. \
+        Deployments will keep working for {} days; please update <code>repo=</code> in the \
+        page's header before then. ~~~~",
+        ss.config().rename_grace_days,
+    );
+    for (wiki, title) in affected {
+        let talk = crate::wp::talk_page(&title);
+        let res = crate::wp::new_section(
+            &ss.client(&wiki),
+            &talk,
+            "Sync source repository moved",
+            &text,
+        )
+        .await;
+        if let Err(e) = res {
+            warn!(?e, %wiki, %talk, "couldn't post rename notice");
+        }
+    }
+}
@@ -0,0 +1,118 @@
+use color_eyre::eyre::{Result, bail, eyre};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use tracing::info;
+
+use crate::github::{
+    branch_head, create_branch, get_contents, put_contents, repo_name, request, send,
+};
+use crate::{SharedState, expand};
+
+/// An edit made on-wiki to a synced page by someone other than us.
+#[derive(Serialize, Clone, Debug)]
+pub struct WikiEdit {
+    pub wiki: String,
+    pub title: String,
+    pub user: String,
+    pub revid: u64,
+    pub comment: String,
+    /// unix timestamp of the edit
+    pub timestamp: u64,
+}
+
+#[derive(Deserialize)]
+struct PullRequest {
+    html_url: String,
+}
+
+/// Propose a manual wiki edit back to the source repository as a pull request,
+/// so git stays the source of truth without losing the hot-fix.
+pub async fn open_pull_request(ss: &SharedState, edit: WikiEdit) -> Result<()> {
+    if ss.github_token.is_none() {
+        bail!("reverse sync needs a GitHub token");
+    }
+
+    let mapping = ss.map.load().iter().find_map(|(source, targets)| {
+        // built artifacts and compiled files have no file in the tree to change
+        let target = targets
+            .iter()
+            .find(|t| t.title == edit.title && t.artifact.is_none() && t.transform.is_none())?;
+        (source.wiki == edit.wiki).then(|| (source.clone(), target.path.clone()))
+    });
+    let Some((source, path)) = mapping else {
+        return Ok(());
+    };
+
+    let repo = repo_name(&source.repo).ok_or_else(|| eyre!("non github URL"))?;
+    let Some(base) = source.ref_.strip_prefix("refs/heads/") else {
+        bail!("can only open pull requests against branches");
+    };
+
+    let wikitext = crate::wp::fetch(&ss.client(&edit.wiki), &edit.title)
+        .await?
+        .slots
+        .main
+        .content;
+
+    let file = get_contents(ss, repo, &path, base)
+        .await?
+        .ok_or_else(|| eyre!("{path} doesn't exist on {base}"))?;
+    let text = String::from_utf8(file.decode()?)?;
+    // the deployed page has the placeholders filled in, which mustn't end up in the tree
+    let Some(mut restored) = expand::restore(&text, &wikitext) else {
+        bail!("couldn't put the placeholders in {path} back into the edited page");
+    };
+    if restored.trim_end_matches('\n') == text.trim_end_matches('\n') {
+        info!(title = %edit.title, "wiki and git already agree");
+        return Ok(());
+    }
+    // put back the newline the wiki trimmed off the end
+    if text.ends_with('\n') && !restored.ends_with('\n') {
+        restored.push('\n');
+    }
+
+    let head = branch_head(ss, repo, base)
+        .await?
+        .ok_or_else(|| eyre!("no branch {base}"))?;
+
+    let branch = format!("usync/{}-{}", edit.wiki, edit.revid);
+    create_branch(ss, repo, &branch, &head).await?;
+
+    let message = format!("Wiki edit to {} by {}", edit.title, edit.user);
+    put_contents(
+        ss,
+        repo,
+        &path,
+        &branch,
+        &message,
+        restored.as_bytes(),
+        Some(&file.sha),
+    )
+    .await?;
+
+    let body = format!(
+        "{} edited [[{}]] on {} (revision {}) with the summary:\n\n> {}\n\n\
+        This pull request carries the change over so it isn't overwritten by the next deployment.",
+        edit.user, edit.title, edit.wiki, edit.revid, edit.comment
+    );
+    let req = request(
+        ss,
+        Method::POST,
+        &format!("https://api.github.com/repos/{repo}/pulls"),
+    )
+    .json(&json!({
+        "title": message,
+        "head": branch,
+        "base": base,
+        "body": body,
+    }));
+    let pr = send(ss, req)
+        .await?
+        .error_for_status()?
+        .json::<PullRequest>()
+        .await?;
+
+    info!(url = %pr.html_url, title = %edit.title, "opened pull request for wiki edit");
+    Ok(())
+}
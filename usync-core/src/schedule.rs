@@ -0,0 +1,148 @@
+//! Slots for running syncs, shared between live deployments and background work.
+
+use tokio::sync::{Semaphore, SemaphorePermit, watch};
+
+use crate::GitHubPush;
+
+/// How urgent a sync is.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Tier {
+    /// pushes, workflow runs and admin-forced syncs, which someone is waiting on
+    Live,
+    /// reconciliation, which can wait until live syncs are done
+    Background,
+}
+
+impl Tier {
+    pub fn of(push: &GitHubPush) -> Tier {
+        // a push coalesced with a live one loses its drift, so it goes live too
+        match push.drift {
+            Some(_) => Tier::Background,
+            None => Tier::Live,
+        }
+    }
+}
+
+/// Limits how many syncs run at once. Live syncs may take every slot, background ones only
+/// their share, and only while no live sync is waiting for one.
+pub struct Permits {
+    slots: Semaphore,
+    background: Semaphore,
+    /// live syncs waiting for a slot
+    live_waiting: watch::Sender<usize>,
+}
+
+/// A slot, given back when dropped.
+pub struct Permit<'a> {
+    _slot: SemaphorePermit<'a>,
+    _background: Option<SemaphorePermit<'a>>,
+}
+
+/// Counts a live sync as waiting for as long as it's alive, even if the wait is cancelled.
+struct Waiting<'a>(&'a watch::Sender<usize>);
+
+impl<'a> Waiting<'a> {
+    fn new(count: &'a watch::Sender<usize>) -> Self {
+        count.send_modify(|n| *n += 1);
+        Waiting(count)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.send_modify(|n| *n -= 1);
+    }
+}
+
+impl Permits {
+    pub fn new(slots: usize, background: usize) -> Self {
+        Permits {
+            slots: Semaphore::new(slots),
+            background: Semaphore::new(background.min(slots)),
+            live_waiting: watch::Sender::new(0),
+        }
+    }
+
+    pub async fn acquire(&self, tier: Tier) -> Permit<'_> {
+        match tier {
+            Tier::Live => {
+                let _waiting = Waiting::new(&self.live_waiting);
+                Permit {
+                    _slot: self.slots.acquire().await.unwrap(),
+                    _background: None,
+                }
+            }
+            Tier::Background => {
+                let background = self.background.acquire().await.unwrap();
+                let mut live = self.live_waiting.subscribe();
+                loop {
+                    let _ = live.wait_for(|&n| n == 0).await;
+                    let slot = self.slots.acquire().await.unwrap();
+                    // slots are handed out in order, so a live sync that queued up behind us gets
+                    // this one when we let go of it
+                    if *live.borrow() == 0 {
+                        break Permit {
+                            _slot: slot,
+                            _background: Some(background),
+                        };
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[test]
+fn test_permits() {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use tokio::sync::oneshot;
+
+    // with the clock paused, a sleep only ends once every other task is stuck waiting
+    let runtime = tokio::runtime::Builder::new_current_thread()
+        .enable_time()
+        .start_paused(true)
+        .build()
+        .unwrap();
+    runtime.block_on(async {
+        let permits = Arc::new(Permits::new(2, 1));
+        let settle = || tokio::time::sleep(Duration::from_secs(1));
+
+        // background work gets at most its share of the slots
+        let background = permits.acquire(Tier::Background).await;
+        let queued = {
+            let permits = permits.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire(Tier::Background).await;
+            })
+        };
+        settle().await;
+        assert!(!queued.is_finished());
+
+        // live syncs can use every slot
+        let live = permits.acquire(Tier::Live).await;
+
+        // and get the next free one before background work does
+        let (acquired, mut got) = oneshot::channel();
+        let (release, done) = oneshot::channel::<()>();
+        {
+            let permits = permits.clone();
+            tokio::spawn(async move {
+                let _permit = permits.acquire(Tier::Live).await;
+                let _ = acquired.send(());
+                let _ = done.await;
+            });
+        }
+        settle().await;
+        assert!(got.try_recv().is_err());
+        drop(background);
+        settle().await;
+        assert!(got.try_recv().is_ok());
+        assert!(!queued.is_finished());
+
+        let _ = release.send(());
+        queued.await.unwrap();
+        drop(live);
+    });
+}
@@ -0,0 +1,139 @@
+//! Checks run once at startup, so a broken setup shows up before the first push does.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use color_eyre::eyre::{Result, bail};
+use reqwest::Method;
+use serde::Serialize;
+use tracing::{error, info};
+
+use crate::SharedState;
+use crate::config::Anchor;
+use crate::github::{request, send};
+use crate::wp;
+
+/// Everything that's wrong with one wiki.
+async fn check_wiki(ss: &SharedState, wiki: &str) -> Vec<String> {
+    let mut problems = Vec::new();
+    let config = &ss.config().wikis[wiki];
+    let client = ss.client(wiki);
+
+    match wp::user_info(&client).await {
+        Ok(info) if info.anon => problems.push("not logged in".to_owned()),
+        Ok(info) => {
+            if info.name != ss.users[wiki] {
+                problems.push(format!(
+                    "logged in as {}, expected {}",
+                    info.name, ss.users[wiki]
+                ));
+            }
+            for right in &config.required_rights {
+                if !info.rights.contains(right) {
+                    problems.push(format!("{} lacks the `{right}` right", info.name));
+                }
+            }
+        }
+        Err(e) => problems.push(format!("couldn't look up our user: {e}")),
+    }
+
+    let (by, page) = match (&config.category, &config.anchor) {
+        (Some(category), _) => ("titles", category.clone()),
+        (None, Anchor::PageId(id)) => ("pageids", id.to_string()),
+        (None, Anchor::Title(title)) => ("titles", title.clone()),
+    };
+    match wp::exists(&client, by, &page).await {
+        Ok(true) => {}
+        Ok(false) => problems.push(format!("discovery page {page} doesn't exist")),
+        Err(e) => problems.push(format!("couldn't look up discovery page {page}: {e}")),
+    }
+
+    problems
+}
+
+/// Whether GitHub can be reached, with our token if we have one.
+async fn check_github(ss: &SharedState) -> Option<String> {
+    let req = request(ss, Method::GET, "https://api.github.com/rate_limit");
+    match send(ss, req).await.and_then(|r| Ok(r.error_for_status()?)) {
+        Ok(_) => None,
+        Err(e) => Some(format!("github: {e}")),
+    }
+}
+
+/// How one dependency is doing, for `/readyz`.
+#[derive(Serialize)]
+pub struct Check {
+    pub ok: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl From<Option<String>> for Check {
+    fn from(error: Option<String>) -> Self {
+        Check {
+            ok: error.is_none(),
+            error,
+        }
+    }
+}
+
+/// Whether we're still logged in to a wiki.
+async fn check_session(ss: &SharedState, wiki: &str) -> Option<String> {
+    match wp::user_info(&ss.client(wiki)).await {
+        Ok(info) if info.anon => Some("not logged in".to_owned()),
+        Ok(_) => None,
+        Err(e) => Some(format!("couldn't look up our user: {e}")),
+    }
+}
+
+/// Whether the map has been built recently and has anything in it.
+fn check_map(ss: &SharedState) -> Option<String> {
+    let Some(rebuilt_at) = *ss.rebuilt_at.lock().unwrap() else {
+        return Some("not built yet".to_owned());
+    };
+    // one missed rebuild is fine, it may just be slow
+    let interval = Duration::from_secs(ss.config().discovery.rebuild_interval_secs);
+    let age = rebuilt_at.elapsed();
+    if age > interval * 2 {
+        return Some(format!("last rebuilt {}s ago", age.as_secs()));
+    }
+    if ss.map.load().is_empty() {
+        return Some("no pages are synced".to_owned());
+    }
+    None
+}
+
+/// Check what syncing depends on right now: each wiki session, GitHub and the map.
+pub async fn readiness(ss: &SharedState) -> BTreeMap<String, Check> {
+    let mut checks = BTreeMap::new();
+    for wiki in ss.config().wikis.keys() {
+        let check = check_session(ss, wiki).await.into();
+        checks.insert(format!("wiki:{wiki}"), check);
+    }
+    checks.insert("github".to_owned(), check_github(ss).await.into());
+    checks.insert("map".to_owned(), check_map(ss).into());
+    checks
+}
+
+/// Check logins, rights, discovery pages and GitHub. Problems are fatal with
+/// `strict_startup`, and only logged otherwise.
+pub async fn run(ss: &SharedState) -> Result<()> {
+    let mut problems = Vec::new();
+    for wiki in ss.config().wikis.keys() {
+        let found = check_wiki(ss, wiki).await;
+        problems.extend(found.into_iter().map(|p| format!("{wiki}: {p}")));
+    }
+    problems.extend(check_github(ss).await);
+
+    if problems.is_empty() {
+        info!("self-check passed");
+        return Ok(());
+    }
+    if ss.config().strict_startup {
+        bail!("self-check failed:\n{}", problems.join("\n"));
+    }
+    for problem in problems {
+        error!(%problem, "self-check failed, starting anyway");
+    }
+    Ok(())
+}
@@ -0,0 +1,175 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures_util::StreamExt;
+use serde::Deserialize;
+use tokio::sync::mpsc::Sender;
+use tokio::task::JoinHandle;
+use tracing::warn;
+
+use crate::SharedState;
+use crate::parser::Reparse;
+use crate::reverse::WikiEdit;
+
+const RECENTCHANGE: &str = "https://stream.wikimedia.org/v2/stream/recentchange";
+
+#[derive(Deserialize)]
+struct Revision {
+    new: u64,
+}
+
+#[derive(Deserialize)]
+struct RecentChange {
+    #[serde(rename = "type")]
+    type_: String,
+    title: String,
+    server_name: String,
+    user: String,
+    #[serde(default)]
+    comment: String,
+    timestamp: u64,
+    revision: Option<Revision>,
+}
+
+/// How many external edits to remember for reporting.
+const EXTERNAL_EDITS: usize = 100;
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+    pub reparse_request: Sender<Reparse>,
+}
+
+fn handle(cx: &Context, data: &str) {
+    let Ok(rc) = serde_json::from_str::<RecentChange>(data) else {
+        return;
+    };
+
+    if !matches!(&*rc.type_, "edit" | "new") {
+        return;
+    }
+
+    let Some(wiki) = cx
+        .ss
+        .config()
+        .wikis
+        .iter()
+        .find(|(_, w)| w.server_name() == Some(&*rc.server_name))
+        .map(|(name, _)| name.clone())
+    else {
+        return;
+    };
+
+    // whatever the approval page lets through now, the whole map has to reflect it. the
+    // usernames page is reread along with it
+    let config = &cx.ss.config().wikis[&wiki];
+    if [&config.approval_page, &config.usernames_page]
+        .iter()
+        .any(|p| p.as_deref() == Some(&*rc.title))
+    {
+        if let Err(e) = cx.reparse_request.try_send(Reparse::All) {
+            warn!(?e, "cannot send to parser!");
+        }
+        return;
+    }
+
+    // only pages of the content models we sync can carry a header
+    if !cx.ss.config().discovery.may_accept_title(&rc.title) {
+        return;
+    }
+
+    let synced = cx.ss.map.load().iter().any(|(source, targets)| {
+        source.wiki == wiki && targets.iter().any(|t| t.title == rc.title)
+    });
+
+    cx.ss.page_edited(&wiki, &rc.title);
+
+    // anything else touching a synced page makes it drift from git
+    if !synced || cx.ss.users.get(&wiki) == Some(&rc.user) {
+        return;
+    }
+    let Some(rev) = rc.revision else {
+        return;
+    };
+
+    let edit = WikiEdit {
+        wiki,
+        title: rc.title,
+        user: rc.user,
+        revid: rev.new,
+        comment: rc.comment,
+        timestamp: rc.timestamp,
+    };
+    warn!(?edit, "external edit to synced page");
+
+    {
+        let mut edits = cx.ss.external_edits.lock().unwrap();
+        if edits.len() == EXTERNAL_EDITS {
+            edits.pop_front();
+        }
+        edits.push_back(edit.clone());
+    }
+
+    if cx.ss.config().reverse_sync {
+        let ss = cx.ss.clone();
+        tokio::spawn(async move {
+            if let Err(e) = crate::reverse::open_pull_request(&ss, edit).await {
+                warn!(?e, "couldn't open pull request for wiki edit");
+            }
+        });
+    }
+}
+
+async fn listen(cx: &Context, last_id: &mut Option<String>) -> color_eyre::Result<()> {
+    let mut req = cx
+        .ss
+        .req
+        .get(RECENTCHANGE)
+        .header("Accept", "text/event-stream");
+
+    // resume where we left off if we got disconnected
+    if let Some(id) = last_id {
+        req = req.header("Last-Event-ID", id.as_str());
+    }
+
+    let mut body = req.send().await?.error_for_status()?.bytes_stream();
+    let mut buf = Vec::new();
+    let mut data = String::new();
+
+    while let Some(chunk) = body.next().await {
+        buf.extend_from_slice(&chunk?);
+        while let Some(pos) = buf.iter().position(|&b| b == b'\n') {
+            let line = buf.drain(..=pos).collect::<Vec<_>>();
+            let line = String::from_utf8_lossy(&line);
+            let line = line.trim_end_matches(['\r', '\n']);
+
+            if line.is_empty() {
+                if !data.is_empty() {
+                    handle(cx, &data);
+                    data.clear();
+                }
+            } else if let Some(d) = line.strip_prefix("data:") {
+                data.push_str(d.trim_start());
+            } else if let Some(id) = line.strip_prefix("id:") {
+                *last_id = Some(id.trim().to_owned());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+pub async fn task(cx: Context) {
+    let mut last_id = None;
+    loop {
+        if let Err(e) = listen(&cx, &mut last_id).await {
+            warn!(?e, "recentchange stream failed");
+        }
+
+        // the server closes the connection periodically, reconnect after a short pause
+        tokio::time::sleep(Duration::from_secs(5)).await;
+    }
+}
+
+pub fn start(cx: Context) -> JoinHandle<()> {
+    tokio::spawn(task(cx))
+}
@@ -0,0 +1,54 @@
+//! Running as a systemd service: credentials and readiness notifications.
+
+use std::path::PathBuf;
+
+use sd_notify::NotifyState;
+use tokio::sync::watch;
+use tracing::{debug, warn};
+
+/// Where to read secrets from. Prefers a `secrets.toml` passed with `LoadCredential=`,
+/// so it doesn't have to be readable by anything but the service. On Toolforge, it's kept in
+/// the tool's home directory, or left out for `toolforge envvars`.
+pub fn secrets_path() -> PathBuf {
+    if let Some(dir) = std::env::var_os("CREDENTIALS_DIRECTORY") {
+        let path = PathBuf::from(dir).join("secrets.toml");
+        if path.exists() {
+            return path;
+        }
+    }
+    crate::toolforge::path("secrets.toml")
+}
+
+fn notify(state: &[NotifyState]) {
+    if let Err(e) = sd_notify::notify(state) {
+        warn!(?e, "couldn't notify systemd");
+    }
+}
+
+/// Tell systemd we're up once the first discovery has finished, then keep the watchdog fed.
+/// Only call this after the server is bound.
+pub async fn ready(mut rebuilt: watch::Receiver<()>) {
+    let _ = rebuilt.changed().await;
+    notify(&[NotifyState::Ready]);
+    debug!("notified systemd");
+
+    let Some(timeout) = sd_notify::watchdog_enabled() else {
+        return;
+    };
+    let mut int = tokio::time::interval(timeout / 2);
+    loop {
+        int.tick().await;
+        notify(&[NotifyState::Watchdog]);
+    }
+}
+
+/// Bracket a config reload, for `Type=notify-reload`.
+pub fn reloading<T>(f: impl FnOnce() -> T) -> T {
+    match NotifyState::monotonic_usec_now() {
+        Ok(now) => notify(&[NotifyState::Reloading, now]),
+        Err(e) => warn!(?e, "couldn't read the monotonic clock"),
+    }
+    let res = f();
+    notify(&[NotifyState::Ready]);
+    res
+}
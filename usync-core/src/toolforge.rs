@@ -0,0 +1,49 @@
+//! Running as a Toolforge tool: the port, home directory and log format it expects.
+
+use std::path::{Path, PathBuf};
+
+use crate::config::Config;
+
+/// The tool's home directory, if we're running on Toolforge.
+pub fn home() -> Option<PathBuf> {
+    std::env::var_os("TOOL_DATA_DIR").map(PathBuf::from)
+}
+
+/// Where to find a file we'd otherwise look for in the working directory.
+pub fn path(name: &str) -> PathBuf {
+    home().unwrap_or_else(|| PathBuf::from(".")).join(name)
+}
+
+/// Listen where `webservice` expects us to, and keep state in the tool's home directory.
+pub fn adapt(config: &mut Config) {
+    let port = std::env::var("PORT").ok();
+    adapt_to(config, home().as_deref(), port.as_deref());
+}
+
+fn adapt_to(config: &mut Config, home: Option<&Path>, port: Option<&str>) {
+    let Some(home) = home else {
+        return;
+    };
+    if let Some(port) = port {
+        config.bind = format!("0.0.0.0:{port}");
+    }
+    if config.state_dir.is_relative() {
+        config.state_dir = home.join(&config.state_dir);
+    }
+}
+
+#[test]
+fn test_adapt() {
+    let mut config = Config::default();
+    adapt_to(&mut config, None, Some("8000"));
+    assert_eq!(config.bind, Config::default().bind);
+    assert_eq!(config.state_dir, Path::new("."));
+
+    adapt_to(
+        &mut config,
+        Some(Path::new("/data/project/usync")),
+        Some("8080"),
+    );
+    assert_eq!(config.bind, "0.0.0.0:8080");
+    assert_eq!(config.state_dir, Path::new("/data/project/usync/."));
+}
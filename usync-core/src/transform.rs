@@ -0,0 +1,455 @@
+//! Changes made to a file on its way to the wiki.
+
+use std::fmt;
+use std::path::Path;
+
+use oxc_allocator::Allocator;
+use oxc_ast::ast::Statement;
+use oxc_codegen::Codegen;
+use oxc_diagnostics::OxcDiagnostic;
+use oxc_parser::Parser;
+use oxc_semantic::SemanticBuilder;
+use oxc_span::SourceType;
+use oxc_transformer::{TransformOptions, Transformer};
+
+use crate::validate::position;
+
+/// A file that isn't valid UTF-8, the only encoding the wiki takes.
+#[derive(Debug)]
+pub struct NotUtf8 {
+    /// where the first invalid byte is
+    pub offset: usize,
+}
+
+impl fmt::Display for NotUtf8 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "the file isn't valid UTF-8 (at byte {})", self.offset)
+    }
+}
+
+impl std::error::Error for NotUtf8 {}
+
+/// Decode a file, dropping a byte order mark. Editors on Windows like to add one, and on the
+/// wiki it would end up in front of the header.
+pub fn decode(bytes: Vec<u8>) -> Result<String, NotUtf8> {
+    let mut text = String::from_utf8(bytes).map_err(|e| NotUtf8 {
+        offset: e.utf8_error().valid_up_to(),
+    })?;
+    if text.starts_with('\u{feff}') {
+        text.drain(..'\u{feff}'.len_utf8());
+    }
+    Ok(text)
+}
+
+#[test]
+fn test_decode() {
+    assert_eq!(decode(b"foo();".to_vec()).unwrap(), "foo();");
+    assert_eq!(decode(b"\xef\xbb\xbffoo();".to_vec()).unwrap(), "foo();");
+    assert_eq!(decode(b"foo(\xff);".to_vec()).unwrap_err().offset, 4);
+    // latin-1 é
+    assert!(decode(b"caf\xe9".to_vec()).is_err());
+}
+
+/// Whether a line is a source map comment, e.g. `//# sourceMappingURL=gadget.js.map`.
+fn is_source_map(line: &str) -> bool {
+    let line = line.trim();
+    let Some(rest) = line
+        .strip_prefix("//")
+        .or_else(|| line.strip_prefix("/*")?.strip_suffix("*/"))
+    else {
+        return false;
+    };
+    let rest = rest.trim_start();
+    let Some(rest) = rest.strip_prefix('#').or_else(|| rest.strip_prefix('@')) else {
+        return false;
+    };
+    rest.trim_start().starts_with("sourceMappingURL=")
+}
+
+/// Take out source map comments. Bundlers add them for files that won't exist on the wiki, which
+/// only gets browsers to request them in vain.
+pub fn strip_source_maps(text: &str) -> String {
+    if !text.contains("sourceMappingURL=") {
+        return text.to_owned();
+    }
+    let mut out = String::with_capacity(text.len());
+    for line in text.split_inclusive('\n') {
+        if !is_source_map(line) {
+            out.push_str(line);
+        }
+    }
+    // don't leave the line before it dangling without its newline
+    if text.ends_with('\n') || !out.ends_with('\n') {
+        return out;
+    }
+    out.pop();
+    out
+}
+
+#[test]
+fn test_strip_source_maps() {
+    assert_eq!(
+        strip_source_maps("foo();\n//# sourceMappingURL=gadget.js.map\n"),
+        "foo();\n"
+    );
+    assert_eq!(
+        strip_source_maps("foo();\n//# sourceMappingURL=gadget.js.map"),
+        "foo();"
+    );
+    assert_eq!(
+        strip_source_maps("a {}\n/*# sourceMappingURL=a.css.map */\n"),
+        "a {}\n"
+    );
+    assert_eq!(
+        strip_source_maps("foo();\n//@ sourceMappingURL=old.map\nbar();\n"),
+        "foo();\nbar();\n"
+    );
+    // only whole comments go
+    let code = "var s = '//# sourceMappingURL=x';\n";
+    assert_eq!(strip_source_maps(code), code);
+}
+
+/// Convert Markdown documentation to wikitext: headings, links, code, lists and emphasis.
+/// Relative links are made absolute against `base`, e.g. the file's directory on GitHub.
+pub fn markdown_to_wikitext(markdown: &str, base: &str) -> String {
+    let mut out = Vec::<String>::new();
+    // the fence that opened the code block we're in, and whether it named a language
+    let mut fence: Option<(&str, bool)> = None;
+    // the indentation and wikitext marker of each list we're nested in
+    let mut lists = Vec::<(usize, char)>::new();
+
+    for line in markdown.lines() {
+        let trimmed = line.trim_start();
+        let indent = line.len() - trimmed.len();
+
+        if let Some((marker, highlighted)) = fence {
+            if trimmed.starts_with(marker) && trimmed.trim_start_matches(marker).trim().is_empty() {
+                out.push(
+                    if highlighted {
+                        "</syntaxhighlight>"
+                    } else {
+                        "</pre>"
+                    }
+                    .to_owned(),
+                );
+                fence = None;
+            } else {
+                out.push(line.to_owned());
+            }
+            continue;
+        }
+
+        let fence_len = trimmed
+            .find(|c| c != '`')
+            .unwrap_or(trimmed.len())
+            .max(trimmed.find(|c| c != '~').unwrap_or(trimmed.len()));
+        if fence_len >= 3 {
+            let (marker, lang) = trimmed.split_at(fence_len);
+            let lang = lang.trim();
+            out.push(match lang {
+                "" => "<pre>".to_owned(),
+                lang => format!("<syntaxhighlight lang=\"{lang}\">"),
+            });
+            fence = Some((marker, !lang.is_empty()));
+            lists.clear();
+            continue;
+        }
+
+        if trimmed.is_empty() {
+            lists.clear();
+            out.push(String::new());
+            continue;
+        }
+
+        let level = trimmed.find(|c| c != '#').unwrap_or(trimmed.len());
+        if (1..=6).contains(&level) && trimmed[level..].starts_with(' ') {
+            let text = trimmed[level..].trim().trim_end_matches('#').trim_end();
+            let marks = "=".repeat(level);
+            out.push(format!("{marks} {} {marks}", inline(text, base)));
+            lists.clear();
+            continue;
+        }
+
+        let rule = trimmed.replace(' ', "");
+        if rule.len() >= 3
+            && ['-', '*', '_']
+                .iter()
+                .any(|&c| rule.trim_matches(c).is_empty())
+        {
+            out.push("----".to_owned());
+            lists.clear();
+            continue;
+        }
+
+        if let Some((marker, text)) = list_item(trimmed) {
+            while lists.last().is_some_and(|(i, _)| *i > indent) {
+                lists.pop();
+            }
+            if lists.last().is_some_and(|(i, _)| *i == indent) {
+                lists.pop();
+            }
+            lists.push((indent, marker));
+            let markers = lists.iter().map(|(_, m)| m).collect::<String>();
+            out.push(format!("{markers} {}", inline(text, base)));
+            continue;
+        }
+
+        // wikitext list items are a single line, so continuations are joined onto them
+        if !lists.is_empty() && indent > 0 {
+            let item = out.last_mut().unwrap();
+            item.push(' ');
+            item.push_str(&inline(trimmed, base));
+            continue;
+        }
+        lists.clear();
+
+        // both take a leading space as preformatted text
+        if indent >= 4 {
+            out.push(format!(" {trimmed}"));
+        } else {
+            out.push(inline(trimmed, base));
+        }
+    }
+    // an unclosed block runs to the end of the file
+    if let Some((_, highlighted)) = fence {
+        out.push(
+            if highlighted {
+                "</syntaxhighlight>"
+            } else {
+                "</pre>"
+            }
+            .to_owned(),
+        );
+    }
+
+    let mut wikitext = out.join("\n");
+    wikitext.push('\n');
+    wikitext
+}
+
+/// The wikitext marker and text of a list item, e.g. `('#', "foo")` for `1. foo`.
+fn list_item(line: &str) -> Option<(char, &str)> {
+    if let Some(text) = ["- ", "* ", "+ "].iter().find_map(|m| line.strip_prefix(m)) {
+        return Some(('*', text.trim()));
+    }
+    let digits = line.find(|c: char| !c.is_ascii_digit())?;
+    let text = line[digits..]
+        .strip_prefix(". ")
+        .or_else(|| line[digits..].strip_prefix(") "))?;
+    (digits > 0).then(|| ('#', text.trim()))
+}
+
+/// Convert inline Markdown: code, links, bold and italics. Anything the wiki would read as
+/// markup of its own is escaped.
+fn inline(text: &str, base: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(c) = rest.chars().next() {
+        if let Some(code) = rest.strip_prefix('`')
+            && let Some(end) = code.find('`')
+        {
+            out.push_str(&format!("<code><nowiki>{}</nowiki></code>", &code[..end]));
+            rest = &code[end + 1..];
+            continue;
+        }
+        if let Some((link, len)) = link(rest, base) {
+            out.push_str(&link);
+            rest = &rest[len..];
+            continue;
+        }
+        if let Some(bold) = rest.strip_prefix("**")
+            && let Some(end) = bold.find("**")
+            && end > 0
+        {
+            out.push_str(&format!("'''{}'''", inline(&bold[..end], base)));
+            rest = &bold[end + 2..];
+            continue;
+        }
+        if let Some(italic) = rest.strip_prefix('*')
+            && !italic.starts_with([' ', '*'])
+            && let Some(end) = italic.find('*')
+            && end > 0
+        {
+            out.push_str(&format!("''{}''", inline(&italic[..end], base)));
+            rest = &italic[end + 1..];
+            continue;
+        }
+        // templates, wikilinks and signatures
+        if let Some(markup) = ["{{", "}}", "[[", "]]", "~~~"]
+            .into_iter()
+            .find(|m| rest.starts_with(m))
+        {
+            out.push_str(&format!("<nowiki>{markup}</nowiki>"));
+            rest = &rest[markup.len()..];
+            continue;
+        }
+        out.push(c);
+        rest = &rest[c.len_utf8()..];
+    }
+    out
+}
+
+/// A `[text](url)` link at the start of `s` as an external link, and how much of `s` it takes
+/// up. Images can't be shown from elsewhere, so `![alt](url)` is left as its alt text.
+fn link(s: &str, base: &str) -> Option<(String, usize)> {
+    let image = s.starts_with('!');
+    let rest = s[usize::from(image)..].strip_prefix('[')?;
+    // link text may hold an image, as badges do
+    let mut depth = 0;
+    let (close, _) = rest.char_indices().find(|&(i, c)| match c {
+        '[' => {
+            depth += 1;
+            false
+        }
+        ']' if depth > 0 => {
+            depth -= 1;
+            false
+        }
+        ']' => rest[i + 1..].starts_with('('),
+        _ => false,
+    })?;
+    let target = &rest[close + 2..];
+    let end = target.find(')')?;
+    let len = usize::from(image) + 1 + close + 2 + end + 1;
+
+    let text = inline(&rest[..close], base);
+    if image {
+        return Some((text, len));
+    }
+    // drop a title, as in `[text](url "title")`
+    let url = target[..end].split_whitespace().next().unwrap_or("");
+    let url = if url.contains("://") || url.starts_with("mailto:") {
+        url.to_owned()
+    } else if url.starts_with('#') {
+        // Markdown and the wiki make anchors out of headings differently
+        return Some((text, len));
+    } else {
+        format!("{base}{}", url.trim_start_matches("./"))
+    };
+    Some((format!("[{url} {text}]"), len))
+}
+
+#[test]
+fn test_markdown_to_wikitext() {
+    let base = "https://github.com/a/b/blob/main/";
+    let markdown = "\
+# Gadget
+
+Does **things**, see [the docs](docs/usage.md) and [MediaWiki](https://www.mediawiki.org).
+[![CI](https://example.org/badge.svg)](https://example.org/ci)
+
+## Install ##
+
+1. Copy `importScript('x')`
+2. Reload
+   the page
+   - nested *item*
+
+```js
+mw.loader.load('x');
+```
+
+---
+Sign with ~~~~ and use {{tl}}.
+";
+    let wikitext = "\
+= Gadget =
+
+Does '''things''', see [https://github.com/a/b/blob/main/docs/usage.md the docs] and [https://www.mediawiki.org MediaWiki].
+[https://example.org/ci CI]
+
+== Install ==
+
+# Copy <code><nowiki>importScript('x')</nowiki></code>
+# Reload the page
+#* nested ''item''
+
+<syntaxhighlight lang=\"js\">
+mw.loader.load('x');
+</syntaxhighlight>
+
+----
+Sign with <nowiki>~~~</nowiki>~ and use <nowiki>{{</nowiki>tl<nowiki>}}</nowiki>.
+";
+    assert_eq!(markdown_to_wikitext(markdown, base), wikitext);
+
+    assert_eq!(
+        markdown_to_wikitext("```\nplain\n", base),
+        "<pre>\nplain\n</pre>\n"
+    );
+}
+
+/// What `transform = tsc` compiles down to. Gadgets may use ES2017 syntax.
+const TSC_TARGET: &str = "es2017";
+
+/// Compile a single-file TypeScript script to JavaScript a wiki can load, returning the first
+/// error. The header is kept as it is, as are placeholders in strings, but other comments may
+/// not be.
+pub fn typescript(text: &str) -> Result<String, String> {
+    let (header, source) = text.split_once('\n').unwrap_or((text, ""));
+    let allocator = Allocator::default();
+    let res = Parser::new(&allocator, source, SourceType::ts()).parse();
+    let describe = |error: &OxcDiagnostic| {
+        let offset = error.labels.first().map(|label| label.offset() as usize);
+        match offset {
+            Some(offset) => {
+                let (line, column) = position(source, offset);
+                // counting the header
+                format!("{error} at line {}, column {column}", line + 1)
+            }
+            None => error.to_string(),
+        }
+    };
+    if let Some(error) = res.diagnostics.first() {
+        return Err(describe(error));
+    }
+    let mut program = res.program;
+
+    let scoping = SemanticBuilder::new()
+        .build(&program)
+        .semantic
+        .into_scoping();
+    let options = TransformOptions::from_target(TSC_TARGET)?;
+    let res = Transformer::new(&allocator, Path::new("index.ts"), &options)
+        .build_with_scoping(scoping, &mut program);
+    if let Some(error) = res.diagnostics.errors().next() {
+        return Err(describe(error));
+    }
+    // type-only imports are gone by now, leaving an `export {}` to keep it a module
+    program.body.retain(|s| {
+        !matches!(s, Statement::ExportNamedDeclaration(e)
+            if e.specifiers.is_empty())
+    });
+    // anything left needs a bundler
+    if program.body.iter().any(|s| s.is_module_declaration()) {
+        return Err("imports and exports can't be deployed as a single script".to_owned());
+    }
+    Ok(format!("{header}\n{}", Codegen::new().build(&program).code))
+}
+
+#[test]
+fn test_typescript() {
+    let ts = "\
+// {{Wikipedia:USync |repo=https://github.com/a/b |ref=refs/heads/main |path=a.ts |transform=tsc}}
+import type { Api } from 'types-mediawiki';
+interface Options { verbose?: boolean }
+const version: string = '/* usync:commit */';
+function run(api: Api, options: Options = {}): void {
+    console.log(options?.verbose ?? false, version);
+}
+";
+    let js = typescript(ts).unwrap();
+    assert!(js.starts_with("// {{Wikipedia:USync"), "{js}");
+    assert!(js.contains("/* usync:commit */"), "{js}");
+    assert!(
+        !js.contains("interface") && !js.contains(": string"),
+        "{js}"
+    );
+    // optional chaining is newer than ES2017
+    assert!(!js.contains("?."), "{js}");
+    crate::validate::javascript(&js).unwrap();
+
+    assert!(typescript("// header\nexport const a = 1;").is_err());
+    let error = typescript("// header\nconst a: = 1;").unwrap_err();
+    assert!(error.ends_with("at line 2, column 10"), "{error}");
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use actix_web::{HttpResponse, Responder, get, web};
+use tracing::error;
+
+use crate::State;
+use crate::audit::LastAttempt;
+
+pub fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[test]
+fn test_escape() {
+    assert_eq!(
+        escape(r#"<a href="x">&</a>"#),
+        "&lt;a href=&quot;x&quot;&gt;&amp;&lt;/a&gt;"
+    );
+}
+
+const SCRIPT: &str = r#"
+async function admin(url) {
+    let token = localStorage.getItem("usyncToken") || prompt("Admin token");
+    if (!token) return;
+    let res = await fetch(url, { method: "POST", headers: { Authorization: "Bearer " + token } });
+    if (res.status == 401) {
+        localStorage.removeItem("usyncToken");
+        alert("Wrong admin token");
+    } else {
+        localStorage.setItem("usyncToken", token);
+        alert(res.ok ? "Queued" : "Failed: " + res.status);
+    }
+}
+"#;
+
+/// Every mapping with how its last sync went, with buttons for the admin endpoints.
+#[get("/ui")]
+pub async fn dashboard(state: web::Data<State>) -> impl Responder {
+    let ss = &state.shared;
+    let (last, last_error) = match (ss.audit.last_attempts(), ss.audit.last_errors()) {
+        (Ok(last), Ok(last_error)) => (last, last_error),
+        (Err(e), _) | (_, Err(e)) => {
+            error!(?e, "couldn't read the audit log");
+            (HashMap::new(), HashMap::new())
+        }
+    };
+
+    let mut rows = ss
+        .map
+        .load()
+        .iter()
+        .flat_map(|(source, targets)| targets.iter().map(move |t| (source.clone(), t.clone())))
+        .collect::<Vec<_>>();
+    rows.sort_by(|a, b| (&a.0.wiki, &a.1.title).cmp(&(&b.0.wiki, &b.1.title)));
+
+    let mut html = String::from(
+        "<!DOCTYPE html><html><head><meta charset=\"utf-8\"><title>usync</title>\
+        <style>body{font-family:sans-serif}table{border-collapse:collapse}\
+        td,th{border:1px solid #ccc;padding:4px 8px;text-align:left}</style>",
+    );
+    let _ = write!(html, "<script>{SCRIPT}</script></head><body><h1>usync</h1>");
+    html.push_str("<p><button onclick=\"admin('/admin/reparse')\">Rebuild map</button></p>");
+    html.push_str(
+        "<table><tr><th>Wiki</th><th>Page</th><th>Source</th><th>Last sync</th>\
+        <th>Last commit</th><th>Last error</th><th></th></tr>",
+    );
+    for (source, target) in rows {
+        let key = (source.wiki.clone(), target.title.clone());
+        let cell = |a: Option<&LastAttempt>, f: fn(&LastAttempt) -> String| {
+            a.map(f).map(|s| escape(&s)).unwrap_or_default()
+        };
+        let attempt = last.get(&key);
+        let error = last_error.get(&key);
+        let sync_url = sync_url(&source.wiki, &target.title);
+        let _ = write!(
+            html,
+            "<tr><td>{}</td><td>{}</td><td>{} {} {}</td><td>{}</td><td>{}</td><td>{}</td>\
+            <td><button onclick=\"admin('{}')\">Sync now</button></td></tr>",
+            escape(&source.wiki),
+            escape(&target.title),
+            escape(&source.repo),
+            escape(&source.ref_),
+            escape(&target.path),
+            cell(attempt, |a| format!("{} ({})", a.timestamp, a.result)),
+            cell(attempt, |a| a.sha.clone()),
+            cell(error, |a| format!(
+                "{}: {}",
+                a.timestamp,
+                a.error.clone().unwrap_or_default()
+            )),
+            escape(&sync_url),
+        );
+    }
+    html.push_str("</table></body></html>");
+
+    HttpResponse::Ok()
+        .content_type("text/html; charset=utf-8")
+        .body(html)
+}
+
+/// The force-sync endpoint for a page.
+fn sync_url(wiki: &str, title: &str) -> String {
+    let params = [("wiki", wiki), ("title", title)];
+    match reqwest::Url::parse_with_params("http://localhost/admin/sync", params) {
+        Ok(url) => format!("/admin/sync?{}", url.query().unwrap_or_default()),
+        Err(_) => String::new(),
+    }
+}
@@ -0,0 +1,1970 @@
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use futures_util::FutureExt;
+use futures_util::future::join_all;
+use serde::Deserialize;
+use tokio::sync::mpsc::Receiver;
+use tokio::sync::watch;
+
+use tokio::sync::mpsc::Sender;
+use tokio::sync::mpsc::error::TrySendError;
+use tokio::task::JoinHandle;
+use tokio::time::Instant;
+use tokio::time::error::Elapsed;
+use tracing::debug;
+use tracing::error;
+use tracing::info;
+use tracing::instrument;
+use tracing::trace;
+use tracing::warn;
+
+use crate::Commits;
+use crate::SharedState;
+use crate::audit::{Attempt, Mapping};
+use crate::deploylog::log_deploy;
+use crate::docs;
+use crate::events::{Activity, publish};
+use crate::expand::{self, Vars};
+use crate::gadgets;
+use crate::github::{
+    Deployment, Fetched, RateLimited, commit_verification, compare_files, compare_status,
+    create_commit_comment, create_deployment, create_status, get_artifact_file, get_file,
+    get_file_retrying, get_raw_fallback, parse_lfs_pointer, renamed_to, repo_name, resolve_lfs,
+};
+use crate::i18n;
+use crate::license;
+use crate::parser::{Reparse, SyncSource, Target};
+use crate::reconcile::{Drift, branch_push};
+use crate::removed;
+use crate::schedule::Tier;
+use crate::transform::{self, NotUtf8};
+use crate::validate;
+use crate::wp::{EditResponse, MainSlot, Missing, Revision};
+use crate::{GitHubPush, Push};
+
+/// initial delay before retrying an edit on a read-only wiki
+const READONLY_BACKOFF: Duration = Duration::from_secs(30);
+/// maintenance windows can last a while, but keep polling at a reasonable pace
+const READONLY_BACKOFF_MAX: Duration = Duration::from_secs(15 * 60);
+/// how many times a sync is retried after github rate limits us
+const RATELIMIT_RETRIES: u32 = 3;
+/// don't hold a sync for longer than this; the hourly primary limit reset can be far off
+const RATELIMIT_WAIT_MAX: Duration = Duration::from_secs(15 * 60);
+/// how many times a sync starts over after someone else edits the page while we do
+const CONFLICT_RETRIES: u32 = 3;
+
+pub struct Context {
+    pub ss: Arc<SharedState>,
+    pub send: Sender<GitHubPush>,
+    pub recv: Receiver<GitHubPush>,
+    pub reparse_request: Sender<Reparse>,
+}
+
+/// Credit authors with a known wiki account by linking to their user page.
+fn attribute(push: &mut GitHubPush, usernames: &HashMap<String, String>) {
+    let link = |login: &str| {
+        usernames
+            .get(login)
+            .map(|user| format!("[[User:{user}|{user}]]"))
+    };
+    let commits = push.commits.iter_mut().chain(&mut push.head_commit);
+    for author in commits.flat_map(|c| [&mut c.author, &mut c.committer]) {
+        if let Some(link) = author.username.as_deref().and_then(link) {
+            author.name = link;
+        }
+    }
+    if let Some(run) = &mut push.run
+        && let Some(link) = link(&run.actor.login)
+    {
+        run.actor.login = link;
+    }
+}
+
+/// The summary for an edit deploying `push` to a page on `wiki`.
+pub fn edit_summary(ss: &SharedState, wiki: &str, push: &GitHubPush) -> String {
+    let mut push = push.clone();
+    if let Some(usernames) = ss.usernames.lock().unwrap().get(wiki) {
+        attribute(&mut push, usernames);
+    }
+    let config = &ss.config().wikis[wiki];
+    parse_webhook(push).into_edit_summary(&config.summary_prefix, &config.language)
+}
+
+#[test]
+fn test_attribute() {
+    let mut push: GitHubPush = serde_json::from_value(serde_json::json!({
+        "compare": "https://github.com/fee1-dead/usync/compare/a...b",
+        "commits": [{
+            "author": { "name": "Alice Example", "username": "alice" },
+            "committer": { "name": "GitHub" },
+            "message": "fix",
+            "added": [],
+            "modified": ["a.js"],
+        }],
+        "ref": "refs/heads/main",
+        "after": "b",
+        "head_commit": null,
+        "repository": {
+            "html_url": "https://github.com/fee1-dead/usync",
+            "contents_url": "",
+        },
+    }))
+    .unwrap();
+    attribute(
+        &mut push,
+        &HashMap::from([("alice".to_owned(), "Alice".to_owned())]),
+    );
+    assert_eq!(push.commits[0].author.name, "[[User:Alice|Alice]]");
+    assert_eq!(push.commits[0].committer.name, "GitHub");
+}
+
+pub fn parse_webhook(p: GitHubPush) -> Push {
+    if let Some(run) = p.run {
+        return Push {
+            commits: Commits::Single(format!("{} #{}", run.name, run.run_number)),
+            authors: vec![run.actor.login],
+            url: run.html_url,
+        };
+    }
+
+    // after a force push, the compare view may be gone and the commit list describes the
+    // rewrite rather than what's being deployed
+    if p.forced
+        && let Some(head) = p.head_commit
+    {
+        let mut authors = vec![head.author.name];
+        if head.committer.name != authors[0] {
+            authors.push(head.committer.name);
+        }
+        return Push {
+            commits: Commits::Single(head.message),
+            authors,
+            url: head.url,
+        };
+    }
+
+    let mut names = HashSet::new();
+    let commits = if p.commits.len() == 1 {
+        Commits::Single(p.commits.first().unwrap().message.clone())
+    } else {
+        Commits::Multiple(p.commits.len())
+    };
+    for commit in p.commits {
+        names.insert(commit.author.name);
+        names.insert(commit.committer.name);
+    }
+
+    Push {
+        commits,
+        authors: names.into_iter().collect(),
+        url: p.compare,
+    }
+}
+
+#[derive(Clone, PartialEq, Eq)]
+pub struct Header {
+    pub repo: String,
+    pub ref_: String,
+    pub path: String,
+    /// wikis the page may live on, by config name or host name. Empty means any.
+    pub wikis: Vec<String>,
+    /// comment on the pushed commit after deploying
+    pub comment: bool,
+    /// deploy `path` from this workflow artifact rather than from the tree
+    pub artifact: Option<String>,
+    /// directory of translations to sync to language subpages
+    pub i18n: Option<String>,
+    /// Markdown file to sync to `<title>/doc` as wikitext
+    pub doc: Option<String>,
+    /// JSON schema in the repo the file has to satisfy
+    pub schema: Option<String>,
+    /// only deploy pushes whose head commit says `[deploy]`
+    pub require_marker: bool,
+    /// only deploy commits with a signature GitHub verified
+    pub require_verified: bool,
+    /// GitHub accounts whose pushes may deploy the page. Empty means anyone's
+    pub pushers: Vec<String>,
+    /// subpage to append a line to for every deployment, e.g. `changelog` or `doc`
+    pub changelog: Option<String>,
+    /// take out `sourceMappingURL` comments. If unset, they're taken out of workflow artifacts
+    pub strip_source_maps: Option<bool>,
+    /// build step to run on the file before deploying it. Only `tsc` for now
+    pub transform: Option<String>,
+}
+
+/// Parse the header for a page with the given content model.
+pub fn parse_header(contentmodel: &str, s: &str) -> Option<Header> {
+    match contentmodel {
+        "javascript" | "css" | "sanitized-css" => parse_js_header(s),
+        "Scribunto" => parse_lua_header(s),
+        "json" => parse_json_header(s),
+        _ => None,
+    }
+}
+
+pub fn parse_js_header(s: &str) -> Option<Header> {
+    parse_template(
+        s.lines()
+            .next()?
+            .trim()
+            .trim_start_matches("//")
+            .trim_start_matches("/*!")
+            .trim_start_matches("/*")
+            .trim_start()
+            .trim_end_matches("*/")
+            .trim_end(),
+    )
+}
+
+pub fn parse_lua_header(s: &str) -> Option<Header> {
+    parse_template(
+        s.lines()
+            .next()?
+            .trim()
+            .trim_start_matches("--[[")
+            .trim_start_matches("--")
+            .trim_start()
+            .trim_end_matches("]]")
+            .trim_end(),
+    )
+}
+
+/// JSON has no comments, so the header lives in a top-level `_usync` string instead.
+pub fn parse_json_header(s: &str) -> Option<Header> {
+    #[derive(Deserialize)]
+    struct Manifest {
+        _usync: String,
+    }
+
+    parse_template(serde_json::from_str::<Manifest>(s).ok()?._usync.trim())
+}
+
+fn parse_template(s: &str) -> Option<Header> {
+    let it = s
+        .strip_prefix("{{Wikipedia:USync")?
+        .strip_suffix("}}")?
+        .trim()
+        .split('|')
+        .map(str::trim);
+
+    let mut repo = None;
+    let mut ref_ = None;
+    let mut path = None;
+    let mut wikis = Vec::new();
+    let mut comment = false;
+    let mut artifact = None;
+    let mut i18n = None;
+    let mut doc = None;
+    let mut schema = None;
+    let mut require_marker = false;
+    let mut require_verified = false;
+    let mut pushers = Vec::new();
+    let mut changelog = None;
+    let mut strip_source_maps = None;
+    let mut transform = None;
+
+    for frag in it {
+        let Some((param, arg)) = frag.split_once('=') else {
+            continue;
+        };
+
+        match param.trim() {
+            "repo" => repo = Some(arg.trim().to_owned()),
+            "ref" => ref_ = Some(arg.trim().to_owned()),
+            "path" => path = Some(arg.trim().to_owned()),
+            "wiki" => wikis = split_list(arg),
+            "pushers" => pushers = split_list(arg),
+            "comment" => comment = matches!(arg.trim(), "yes" | "true" | "1"),
+            "artifact" => artifact = Some(arg.trim().to_owned()),
+            "i18n" => i18n = Some(arg.trim().trim_end_matches('/').to_owned()),
+            "doc" => doc = Some(arg.trim().to_owned()).filter(|s| !s.is_empty()),
+            "schema" => schema = Some(arg.trim().to_owned()),
+            "require_marker" => require_marker = matches!(arg.trim(), "yes" | "true" | "1"),
+            "require_verified" => require_verified = matches!(arg.trim(), "yes" | "true" | "1"),
+            "transform" => transform = Some(arg.trim().to_owned()).filter(|s| !s.is_empty()),
+            "sourcemaps" => match arg.trim() {
+                "strip" => strip_source_maps = Some(true),
+                "keep" => strip_source_maps = Some(false),
+                _ => {}
+            },
+            "changelog" => {
+                changelog = Some(arg.trim().trim_matches('/').to_owned()).filter(|s| !s.is_empty())
+            }
+            _ => {}
+        }
+    }
+
+    Some(Header {
+        repo: repo?,
+        ref_: ref_?,
+        path: path?,
+        wikis,
+        comment,
+        artifact,
+        i18n,
+        doc,
+        schema,
+        require_marker,
+        require_verified,
+        pushers,
+        changelog,
+        strip_source_maps,
+        transform,
+    })
+}
+
+fn split_list(arg: &str) -> Vec<String> {
+    arg.split(',')
+        .map(str::trim)
+        .filter(|w| !w.is_empty())
+        .map(ToOwned::to_owned)
+        .collect()
+}
+
+#[test]
+fn test_header_parse() {
+    let headers = [
+        "// {{Wikipedia:USync | repo = https://github.com/fee1-dead/usync |ref = refs/heads/main |path=test.js}}",
+        "/* {{Wikipedia:USync | repo = https://github.com/fee1-dead/usync |ref = refs/heads/main |path=test.js}} */",
+        "/*! {{Wikipedia:USync | repo = https://github.com/fee1-dead/usync |ref = refs/heads/main |path=test.js}} */",
+    ];
+
+    for &h in &headers {
+        let parsed = parse_js_header(h);
+        assert!(parsed.is_some());
+
+        let parsed = parsed.unwrap();
+        assert_eq!("https://github.com/fee1-dead/usync", parsed.repo);
+        assert_eq!("refs/heads/main", parsed.ref_);
+        assert_eq!("test.js", parsed.path);
+    }
+}
+
+#[test]
+fn test_header_parse_options() {
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |require_marker = yes}}",
+    )
+    .unwrap();
+    assert!(header.require_marker);
+    assert!(!header.require_verified);
+    assert!(header.pushers.is_empty());
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |pushers = alice, bob}}",
+    )
+    .unwrap();
+    assert_eq!(header.pushers, ["alice", "bob"]);
+    assert_eq!(header.changelog, None);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |changelog = /changelog}}",
+    )
+    .unwrap();
+    assert_eq!(header.changelog.as_deref(), Some("changelog"));
+    assert_eq!(header.doc, None);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |doc = README.md}}",
+    )
+    .unwrap();
+    assert_eq!(header.doc.as_deref(), Some("README.md"));
+}
+
+#[test]
+fn test_header_parse_wiki() {
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |wiki = enwiki, meta.wikimedia.org}}",
+    )
+    .unwrap();
+    assert_eq!(header.wikis, ["enwiki", "meta.wikimedia.org"]);
+    assert!(!header.comment);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a |comment=yes}}",
+    )
+    .unwrap();
+    assert!(header.comment);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=dist/gadget.js |artifact=gadget}}",
+    )
+    .unwrap();
+    assert_eq!(header.artifact.as_deref(), Some("gadget"));
+    assert_eq!(header.strip_source_maps, None);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=dist/gadget.js |artifact=gadget |sourcemaps=keep}}",
+    )
+    .unwrap();
+    assert_eq!(header.strip_source_maps, Some(false));
+    assert_eq!(header.transform, None);
+
+    let header = parse_js_header(
+        "// {{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=src/gadget.ts |transform=tsc}}",
+    )
+    .unwrap();
+    assert_eq!(header.transform.as_deref(), Some("tsc"));
+}
+
+#[test]
+fn test_header_parse_models() {
+    let template =
+        "{{Wikipedia:USync |repo=https://github.com/fee1-dead/usync |ref=refs/heads/main |path=a}}";
+
+    assert!(parse_header("css", &format!("/* {template} */")).is_some());
+    assert!(parse_header("Scribunto", &format!("-- {template}\nreturn {{}}")).is_some());
+    assert!(parse_header("Scribunto", &format!("--[[ {template} ]]")).is_some());
+    assert!(parse_header("json", &format!(r#"{{"_usync": "{template}", "a": 1}}"#)).is_some());
+    assert!(parse_header("wikitext", &format!("<!-- {template} -->")).is_none());
+}
+
+pub enum Outcome {
+    /// the edit went through. `nochange` is set if the wiki already had this content, `comment`
+    /// if the page asks for a comment on the commit, `purge` if pages using it only pick up the
+    /// change once purged, `changelog` if the page keeps a changelog on that subpage. `summary`
+    /// is the one the edit was made with
+    Deployed {
+        revid: Option<u64>,
+        nochange: bool,
+        comment: bool,
+        purge: bool,
+        changelog: Option<String>,
+        summary: String,
+    },
+    /// there was nothing to deploy
+    Done,
+    /// the sync failed for the given reason
+    Failed(String),
+    /// the wiki is read-only, so the edit should be retried later
+    ReadOnly,
+    /// github rate limited us, so the sync should be retried after the delay
+    RateLimited(Duration),
+    /// the page was edited since we fetched it, so the sync should start over
+    Conflicted,
+    /// the page differs from the file, but we were only asked to report that
+    Drifted,
+    /// the page was deleted since it was mapped
+    Gone,
+    /// the push removed the file, so the page was taken off the map
+    Removed,
+    /// the edit looks like a mistake and waits for an admin to confirm it
+    Held(String),
+    /// an admin blocked syncing the page from this branch
+    Blocked,
+}
+
+/// The parts of package.json we use.
+#[derive(Deserialize)]
+struct Manifest {
+    version: String,
+}
+
+/// `version` from the repository's package.json at a commit, for `/* usync:version */`.
+async fn package_version(ss: &SharedState, repo: &str, sha: &str) -> Option<String> {
+    let url = format!("https://api.github.com/repos/{repo}/contents/package.json");
+    match get_file(ss, &url, sha, None).await {
+        Ok(Fetched::Content { text, .. }) => match serde_json::from_str::<Manifest>(&text) {
+            Ok(manifest) => Some(manifest.version),
+            Err(e) => {
+                warn!(?e, "package.json has no usable version");
+                None
+            }
+        },
+        Ok(Fetched::NotModified) => None,
+        Err(e) => {
+            warn!(?e, "couldn't get package.json");
+            None
+        }
+    }
+}
+
+/// The JSON schema a file declares, from the pushed commit.
+async fn fetch_schema(
+    ss: &SharedState,
+    repo: &str,
+    sha: &str,
+    path: &str,
+) -> Result<serde_json::Value, Outcome> {
+    let url = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let text = match get_file(ss, &url, sha, None).await {
+        Ok(Fetched::Content { text, .. }) => text,
+        Ok(Fetched::NotModified) => {
+            error!(%path, "schema came back unmodified without an etag");
+            return Err(Outcome::Failed("couldn't get the JSON schema".into()));
+        }
+        Err(e) => {
+            error!(?e, %path, "couldn't get the schema");
+            return Err(Outcome::Failed("couldn't get the JSON schema".into()));
+        }
+    };
+    serde_json::from_str(&text).map_err(|e| {
+        warn!(?e, %path, "schema isn't JSON");
+        Outcome::Failed(format!("the JSON schema doesn't parse: {e}"))
+    })
+}
+
+/// Get a file from the pushed commit, along with its ETag. Fails with the outcome of the sync
+/// if the file is unchanged or couldn't be fetched.
+async fn fetch_from_tree(
+    ss: &SharedState,
+    push: &GitHubPush,
+    repo: &str,
+    path: &str,
+    key: &(String, String),
+    retries: &AtomicU32,
+) -> Result<(String, Option<String>), Outcome> {
+    // e.g. https://api.github.com/repos/fee1-dead/usync/contents/test.js
+    let file_url = format!("https://api.github.com/repos/{repo}/contents/{path}");
+    let file_url2 = push.repository.contents_url.replace("{+path}", path);
+
+    if file_url != file_url2 {
+        warn!(?file_url, ?file_url2, "urls mismatched");
+        return Err(Outcome::Done);
+    }
+
+    // if the file is still what we last confirmed on the wiki, we don't need to look at the wiki
+    // at all. reconciliation is there to catch edits made on the wiki, so it always looks
+    let etag = match push.drift {
+        Some(_) => None,
+        None => ss.etags.lock().unwrap().get(key).cloned(),
+    };
+    // fetch at the pushed commit rather than the branch, which may have moved on since
+    let fetched = get_file_retrying(ss, &file_url, &push.after, etag.as_deref(), retries).await;
+    let (text, etag) = match fetched {
+        Ok(Fetched::NotModified) => {
+            info!("unchanged since the last sync");
+            return Err(Outcome::Done);
+        }
+        Ok(Fetched::Content { text, etag }) => (text, etag),
+        // the fallback would get the same bytes
+        Err(e) if e.is::<NotUtf8>() => {
+            error!(%e, "refusing to deploy");
+            return Err(Outcome::Failed(e.to_string()));
+        }
+        Err(e) => {
+            warn!(
+                ?e,
+                "couldn't get content from the github api, trying raw.githubusercontent.com"
+            );
+            match get_raw_fallback(ss, repo, &push.after, path).await {
+                // the etag doesn't carry over between the two
+                Ok(text) => (text, None),
+                Err(e2) => {
+                    if let Some(RateLimited { retry_after }) = e.downcast_ref() {
+                        return Err(Outcome::RateLimited(*retry_after));
+                    }
+                    error!(?e2, "couldn't get content from github");
+                    return Err(Outcome::Failed("couldn't get the file from GitHub".into()));
+                }
+            }
+        }
+    };
+
+    // files stored in Git LFS come back as a pointer to the real content
+    let text = match parse_lfs_pointer(&text) {
+        Some(pointer) => match resolve_lfs(ss, repo, &pointer).await {
+            Ok(text) => text,
+            Err(e) if e.is::<NotUtf8>() => {
+                error!(%e, "refusing to deploy");
+                return Err(Outcome::Failed(e.to_string()));
+            }
+            Err(e) => {
+                error!(?e, oid = %pointer.oid, "couldn't resolve git lfs pointer, refusing to deploy it");
+                return Err(Outcome::Failed(
+                    "couldn't resolve the Git LFS pointer".into(),
+                ));
+            }
+        },
+        None => text,
+    };
+    Ok((text, etag))
+}
+
+/// Whether the push is older than the commit last synced to the page.
+async fn is_stale(ss: &SharedState, push: &GitHubPush, repo: &str, mapping: &Mapping<'_>) -> bool {
+    let last = match ss.audit.last_synced(mapping) {
+        Ok(Some(last)) => last,
+        Ok(None) => return false,
+        Err(e) => {
+            warn!(?e, "couldn't look up last synced commit");
+            return false;
+        }
+    };
+    if last == push.after {
+        return false;
+    }
+
+    match compare_status(ss, repo, &last, &push.after)
+        .await
+        .as_deref()
+    {
+        Ok("behind") => {
+            info!(%last, after = %push.after, "push is older than the deployed commit, skipping");
+            true
+        }
+        Ok("diverged") if !push.forced => {
+            warn!(%last, after = %push.after, "push diverged from the deployed commit");
+            false
+        }
+        Ok(_) => false,
+        Err(e) => {
+            warn!(?e, "couldn't compare with the deployed commit");
+            false
+        }
+    }
+}
+
+/// What the head commit has to say to deploy to pages with `require_marker`.
+const DEPLOY_MARKER: &str = "[deploy]";
+
+pub async fn sort(
+    ss: Arc<SharedState>,
+    mut push: GitHubPush,
+    wiki: String,
+    target: Target,
+    fetch_retries: &AtomicU32,
+) -> Outcome {
+    let title = &target.title;
+    let mut path = &target.path;
+    let renamed;
+
+    if let Err(outcome) = check_blocked(&ss, &push, &wiki, title) {
+        return outcome;
+    }
+
+    if push.run.is_none() {
+        // the file must have been modified on Git's side for us to trigger an update
+        if !push.touches(path) {
+            if !push.removes(path) || push.skips_sync() {
+                info!("not modified");
+                return Outcome::Done;
+            }
+            // a rename shows up as the file being removed and another added
+            renamed = match rename_of(&ss, &push, path).await {
+                Ok(Some(to)) => to,
+                Ok(None) => return removed::removed(&ss, &push, &wiki, &target).await,
+                Err(outcome) => return outcome,
+            };
+            info!(to = %renamed, "file renamed, deploying from its new path");
+            path = &renamed;
+        }
+        // only keep the commits touching the file for the summary. without file lists we
+        // can't tell, so keep them all
+        if push.changed.is_none() && !push.forced {
+            push.commits
+                .retain(|c| c.added.contains(path) || c.modified.contains(path));
+        }
+        if push.skips_sync() {
+            info!("skipped by commit message");
+            return Outcome::Done;
+        }
+    }
+
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        warn!(?push.repository.html_url, "non github URL");
+        return Outcome::Done;
+    };
+
+    let mapping = Mapping {
+        wiki: &wiki,
+        repo: &push.repository.html_url,
+        ref_: &push.ref_,
+        path,
+        title,
+    };
+    // deliveries can arrive out of order, don't roll the page back to an older commit
+    if is_stale(&ss, &push, repo, &mapping).await {
+        return Outcome::Done;
+    }
+
+    let key = (wiki.clone(), title.clone());
+    let (newtext, etag) = match (&push.run, &target.artifact) {
+        (None, None) => match fetch_from_tree(&ss, &push, repo, path, &key, fetch_retries).await {
+            Ok(fetched) => fetched,
+            Err(outcome) => return outcome,
+        },
+        (Some(run), Some(artifact)) => {
+            match get_artifact_file(&ss, &run.artifacts_url, artifact, path).await {
+                Ok(text) => (text, None),
+                Err(e) if e.is::<NotUtf8>() => {
+                    error!(%e, %artifact, "refusing to deploy");
+                    return Outcome::Failed(e.to_string());
+                }
+                Err(e) => {
+                    error!(?e, %artifact, "couldn't get content from the workflow artifact");
+                    return Outcome::Failed(
+                        "couldn't get the file from the workflow artifact".into(),
+                    );
+                }
+            }
+        }
+        // pushes don't deploy artifacts, and workflow runs don't deploy files in the tree
+        _ => return Outcome::Done,
+    };
+    // fill in placeholders, but compare against the file as written
+    let template = newtext;
+    let version = if expand::uses(&template, "version") {
+        package_version(&ss, repo, &push.after).await
+    } else {
+        None
+    };
+    let vars = Vars {
+        commit: &push.after,
+        ref_: &push.ref_,
+        date: expand::today(),
+        version,
+    };
+    let newtext = expand::expand(&template, &vars);
+
+    // the wiki now has the content of the pushed commit
+    let confirmed = || {
+        if let Some(etag) = etag.clone() {
+            ss.etags.lock().unwrap().insert(key.clone(), etag);
+        }
+        if let Err(e) = ss.audit.set_last_synced(&mapping, &push.after) {
+            warn!(?e, "couldn't record last synced commit");
+        }
+    };
+
+    let client = &ss.client(&wiki);
+    let prefetched = ss.prefetched.lock().unwrap().remove(&key);
+    let page = match prefetched {
+        Some((at, page)) if at.elapsed() < PREFETCH_TTL => Ok(page),
+        _ => crate::wp::fetch_page(client, title).await,
+    };
+    let page = match page {
+        Ok(page) => page,
+        Err(e) => {
+            error!(?e, "couldn't fetch");
+            return Outcome::Failed("couldn't fetch the wiki page".into());
+        }
+    };
+    // titles are normalized and redirects followed, the page might go by another name now
+    let canonical = page.title.clone();
+    let Revision {
+        slots,
+        revid: base_revid,
+        timestamp: base_timestamp,
+        user: last_editor,
+    } = match page.latest() {
+        Ok(page) => page,
+        Err(e) if e.is::<Missing>() => {
+            warn!("page is gone, dropping it from the map");
+            if !push.dry_run {
+                let reparse = Reparse::Title {
+                    wiki: wiki.clone(),
+                    title: title.clone(),
+                };
+                let _ = ss.reparse.send(reparse).await;
+            }
+            return Outcome::Gone;
+        }
+        Err(e) => {
+            error!(?e, "couldn't fetch");
+            return Outcome::Failed("couldn't fetch the wiki page".into());
+        }
+    };
+    let MainSlot {
+        content: orig_src,
+        contentmodel,
+    } = slots.main;
+    debug!(%base_revid, %base_timestamp, ?last_editor, "fetched page");
+    if canonical != *title {
+        info!(%canonical, "page goes by another title, editing that one");
+        if !push.dry_run {
+            let reparse = Reparse::Title {
+                wiki: wiki.clone(),
+                title: title.clone(),
+            };
+            let _ = ss.reparse.send(reparse).await;
+        }
+    }
+    let title = &canonical;
+    // refetch the info on-wiki to compare
+    let Some(header) = parse_header(&contentmodel, &orig_src) else {
+        error!("couldn't parse on-wiki header");
+        return Outcome::Failed("couldn't parse the header on the wiki page".into());
+    };
+
+    // check again that the reference, the repo url and the path match
+    if push.ref_ != header.ref_
+        || push.repository.html_url != ss.canonical_repo(&header.repo)
+        || target.path != header.path
+        || target.artifact != header.artifact
+    {
+        error!("2nd comparison failed");
+        return Outcome::Failed("the wiki page syncs from a different source".into());
+    }
+
+    trace!(%newtext, %orig_src);
+
+    if let Err(outcome) = header_gates(&ss, &push, repo, &header).await {
+        return outcome;
+    }
+
+    // the wiki gets the output, so that's what's compared
+    let (template, newtext) = match header.transform.as_deref() {
+        None => (template, newtext),
+        Some("tsc") => match transform::typescript(&template) {
+            Ok(template) => {
+                let newtext = expand::expand(&template, &vars);
+                (template, newtext)
+            }
+            Err(e) => {
+                warn!(%e, "couldn't compile the file");
+                return Outcome::Failed(format!("couldn't compile the TypeScript: {e}"));
+            }
+        },
+        Some(other) => return Outcome::Failed(format!("unknown transform {other}")),
+    };
+    // built files point at source maps that won't be on the wiki
+    let (template, newtext) = if header
+        .strip_source_maps
+        .unwrap_or(target.artifact.is_some())
+    {
+        (
+            transform::strip_source_maps(&template),
+            transform::strip_source_maps(&newtext),
+        )
+    } else {
+        (template, newtext)
+    };
+
+    // no need to edit if nothing changed, other than the values of placeholders
+    if newtext == orig_src || expand::same_template(&template, &orig_src) {
+        info!("nothing changed");
+        // a dry run leaves no trace, not even the commit the page was last seen at
+        if !push.dry_run {
+            confirmed();
+        }
+        return Outcome::Done;
+    }
+
+    if push.drift == Some(Drift::Report) {
+        warn!("page drifted from its branch");
+        return Outcome::Drifted;
+    }
+
+    // ensure that the github side has the same header. a renamed file may name its new path
+    // already, which fixes the mapping once deployed
+    let comment = header.comment;
+    let changelog = header.changelog.clone();
+    let schema = header.schema.clone();
+    let file_header = parse_header(&contentmodel, &newtext);
+    let healed = file_header.as_ref().is_some_and(|h| h.path == *path);
+    let mut header = header;
+    if healed {
+        header.path.clone_from(path);
+    }
+    if file_header != Some(header) {
+        info!("header mismatched");
+        return Outcome::Failed("the file's header doesn't match the one on the wiki".into());
+    }
+
+    let invalid = match &*contentmodel {
+        "javascript" => validate::javascript(&newtext).err(),
+        "sanitized-css" if ss.config().strict_template_styles => {
+            validate::sanitized_css(&newtext).err()
+        }
+        "css" | "sanitized-css" => validate::css(&newtext).err(),
+        "Scribunto" => validate::lua(&newtext).err(),
+        "json" => match &schema {
+            Some(schema) => match fetch_schema(&ss, repo, &push.after, schema).await {
+                Ok(schema) => validate::json(&newtext, Some(&schema)).err(),
+                Err(outcome) => return outcome,
+            },
+            None => validate::json(&newtext, None).err(),
+        },
+        _ => None,
+    };
+    if let Some(e) = invalid {
+        warn!(%e, "refusing to deploy a file that doesn't parse");
+        return Outcome::Failed(format!("the file doesn't parse: {e}"));
+    }
+    if let Err(outcome) = license::check(&ss, repo, &push.after, &newtext).await {
+        return outcome;
+    }
+
+    // a botched build or a deleted file shouldn't blank a live page
+    if !push.confirmed
+        && let Some(percent) = shrinkage(&orig_src, &newtext, ss.config().max_shrink_percent)
+    {
+        warn!(percent, "holding a sync that would shrink the page");
+        return Outcome::Held(format!(
+            "the page would shrink by {percent}%, confirm to deploy"
+        ));
+    }
+
+    let summary = edit_summary(&ss, &wiki, &push);
+
+    if push.dry_run {
+        info!(%summary, "dry run, would edit the page");
+        return Outcome::Done;
+    }
+
+    // checked against the real page, but made on the staging one
+    let staged = ss.config().staging.redirect(&wiki, title);
+    let staging_client;
+    let (client, title): (_, &str) = match &staged {
+        Some((wiki, title)) => {
+            info!(%wiki, %title, "staging, editing elsewhere");
+            staging_client = ss.client(wiki);
+            (&staging_client, title)
+        }
+        None => (client, title),
+    };
+
+    let mut params = vec![
+        ("action", "edit"),
+        ("title", title),
+        ("text", &newtext),
+        ("summary", &summary),
+        ("bot", "1"),
+        // ("contentformat", "text/javascript"),
+        // ("contentmodel", "javascript"),
+    ];
+    // staging pages are created as needed. the real page may have been edited since it was
+    // checked, which the wiki tells us rather than overwriting the edit
+    let base_revid = base_revid.to_string();
+    if staged.is_none() {
+        params.push(("nocreate", "1"));
+        params.push(("baserevid", &base_revid));
+        params.push(("basetimestamp", &base_timestamp));
+    }
+    let res = crate::wp::edit(client, &params).await;
+
+    match res {
+        Ok(EditResponse { error: Some(e), .. }) if e.code == "readonly" => {
+            warn!(info = %e.info, "wiki is read-only");
+            Outcome::ReadOnly
+        }
+        Ok(EditResponse { error: Some(e), .. }) if e.code == "editconflict" => {
+            warn!("page was edited while syncing");
+            Outcome::Conflicted
+        }
+        Ok(EditResponse { error: Some(e), .. }) => {
+            error!(?e, "edit");
+            Outcome::Failed(format!("edit failed: {}", e.info))
+        }
+        Ok(EditResponse {
+            edit: Some(edit), ..
+        }) if edit.result == "Success" => {
+            // the real page still has the old content while staging
+            if staged.is_none() {
+                confirmed();
+                if *path != target.path {
+                    renamed_deployed(&ss, &wiki, &canonical, &target.path, path, healed).await;
+                }
+            }
+            Outcome::Deployed {
+                revid: edit.newrevid,
+                nochange: edit.nochange,
+                comment,
+                purge: matches!(&*contentmodel, "Scribunto" | "sanitized-css"),
+                changelog,
+                summary,
+            }
+        }
+        Ok(res) => {
+            error!(?res, "unexpected edit response");
+            Outcome::Failed("unexpected edit response".into())
+        }
+        Err(e) => {
+            error!(?e, "edit");
+            Outcome::Failed("edit request failed".into())
+        }
+    }
+}
+
+/// Check that an admin didn't block syncing the page from the push's branch. Fails with the
+/// outcome of the sync if they did.
+fn check_blocked(
+    ss: &SharedState,
+    push: &GitHubPush,
+    wiki: &str,
+    title: &str,
+) -> Result<(), Outcome> {
+    let source = SyncSource {
+        wiki: wiki.to_owned(),
+        repo: push.repository.html_url.clone(),
+        ref_: push.ref_.clone(),
+    };
+    match ss.audit.is_blocked(&source, title) {
+        Ok(false) => Ok(()),
+        Ok(true) => {
+            info!(%wiki, %title, "mapping blocked by an admin");
+            Err(Outcome::Blocked)
+        }
+        Err(e) => {
+            // better to miss a deployment than to go through with a blocked one
+            error!(?e, "couldn't check the denylist");
+            Err(Outcome::Failed(
+                "couldn't check whether the mapping is blocked".into(),
+            ))
+        }
+    }
+}
+
+/// Check that the push may change the page, going by what its header asks for. Fails with the
+/// outcome of the sync if not.
+pub async fn header_gates(
+    ss: &SharedState,
+    push: &GitHubPush,
+    repo: &str,
+    header: &Header,
+) -> Result<(), Outcome> {
+    let head = push.head_commit.as_ref().or(push.commits.last());
+    if header.require_marker && !head.is_some_and(|c| c.has_marker(DEPLOY_MARKER)) {
+        info!("head commit doesn't ask for a deployment");
+        return Err(Outcome::Done);
+    }
+    if !header.pushers.is_empty() {
+        let Some(sender) = &push.sender else {
+            info!("can't tell who pushed, leaving the page for a push we can");
+            return Err(Outcome::Done);
+        };
+        if !header
+            .pushers
+            .iter()
+            .any(|p| p.eq_ignore_ascii_case(&sender.login))
+        {
+            warn!(sender = %sender.login, "refusing a push from an account that may not deploy");
+            return Err(Outcome::Failed(format!(
+                "{} may not deploy this page",
+                sender.login
+            )));
+        }
+    }
+    if header.require_verified {
+        match commit_verification(ss, repo, &push.after).await {
+            Ok(v) if v.verified => {}
+            Ok(v) => {
+                warn!(reason = %v.reason, "refusing to deploy an unverified commit");
+                return Err(Outcome::Failed(format!(
+                    "the commit's signature isn't verified ({})",
+                    v.reason
+                )));
+            }
+            Err(e) => {
+                if let Some(RateLimited { retry_after }) = e.downcast_ref() {
+                    return Err(Outcome::RateLimited(*retry_after));
+                }
+                error!(?e, "couldn't check the commit's signature");
+                return Err(Outcome::Failed(
+                    "couldn't check the commit's signature".into(),
+                ));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Where the push moved `path`, or `None` if it deleted it. Fails with the outcome of the sync if
+/// we can't tell.
+async fn rename_of(
+    ss: &SharedState,
+    push: &GitHubPush,
+    path: &str,
+) -> Result<Option<String>, Outcome> {
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return Ok(None);
+    };
+    match renamed_to(ss, repo, &push.before, &push.after, path).await {
+        Ok(to) => Ok(to),
+        Err(e) => {
+            if let Some(RateLimited { retry_after }) = e.downcast_ref() {
+                return Err(Outcome::RateLimited(*retry_after));
+            }
+            error!(?e, "couldn't look for a rename");
+            Err(Outcome::Failed(
+                "couldn't tell whether the file was renamed".into(),
+            ))
+        }
+    }
+}
+
+/// Follow up on deploying a renamed file. If the file's header names its new path, the page's
+/// header now does too and only the map has to catch up. Otherwise ask the page's maintainers
+/// to fix the header, as later pushes won't reach the page until then.
+async fn renamed_deployed(
+    ss: &SharedState,
+    wiki: &str,
+    title: &str,
+    from: &str,
+    to: &str,
+    healed: bool,
+) {
+    if healed {
+        let reparse = Reparse::Title {
+            wiki: wiki.to_owned(),
+            title: title.to_owned(),
+        };
+        let _ = ss.reparse.send(reparse).await;
+        return;
+    }
+    let talk = crate::wp::talk_page(title);
+    let text = format!(
+        "The file this page is synced from was moved from <code>{from}</code> to \
+        <code>{to}</code>. It was deployed from its new path this time, but later changes \
+        won't be until <code>path=</code> in the header is updated, both in the file and on \
+        the page. ~~~~"
+    );
+    let res =
+        crate::wp::new_section(&ss.client(wiki), &talk, "Sync source file moved", &text).await;
+    if let Err(e) = res {
+        warn!(?e, %wiki, %talk, "couldn't post rename notice");
+    }
+}
+
+/// By how many percent `new` is smaller than `old`, if that's more than allowed or leaves
+/// nothing but the header.
+fn shrinkage(old: &str, new: &str, max_percent: u64) -> Option<u64> {
+    if new.len() >= old.len() {
+        return None;
+    }
+    let percent = ((old.len() - new.len()) * 100 / old.len()) as u64;
+    let nearly_empty = new.trim().lines().count() <= 1 && old.trim().lines().count() > 1;
+    (max_percent < 100 && (percent > max_percent || nearly_empty)).then_some(percent)
+}
+
+#[test]
+fn test_shrinkage() {
+    let header =
+        "// {{Wikipedia:USync |repo=https://github.com/a/b |ref=refs/heads/main |path=a.js}}";
+    let old = format!("{header}\n{}", "mw.log(1);\n".repeat(100));
+    assert_eq!(shrinkage(&old, &old, 80), None);
+    assert_eq!(shrinkage(&old, &old[..old.len() / 2], 80), None);
+    assert_eq!(shrinkage(&old, &old[..old.len() / 10], 80), Some(90));
+    assert_eq!(shrinkage(&old, &old[..old.len() / 10], 100), None);
+
+    let small = format!("{header}\nmw.log(1);");
+    assert_eq!(shrinkage(&small, header, 80), Some(11));
+}
+
+/// Refresh loader pages on other wikis, so a globally loaded script takes effect promptly.
+async fn refresh_loaders(ss: &SharedState, wiki: &str, title: &str) {
+    for loader in &ss.config().wikis[wiki].loaders {
+        if loader.source != title {
+            continue;
+        }
+
+        let Some(lock) = ss.clients.get(&loader.wiki) else {
+            warn!(wiki = %loader.wiki, "loader on an unknown wiki");
+            continue;
+        };
+        let client = lock.lock().unwrap().clone();
+
+        let res = if loader.touch {
+            crate::wp::null_edit(&client, &loader.title).await
+        } else {
+            crate::wp::purge(&client, &loader.title, false).await
+        };
+
+        if let Err(e) = res {
+            warn!(?e, wiki = %loader.wiki, title = %loader.title, "couldn't refresh loader");
+        }
+    }
+}
+
+/// Purge a deployed module or stylesheet along with the pages configured as using it, so the
+/// change shows up without waiting on the job queue.
+async fn purge_deployed(
+    ss: &SharedState,
+    wiki: &str,
+    title: &str,
+    dependents: Option<&Vec<String>>,
+) {
+    let client = ss.client(wiki);
+    let pages = std::iter::once(title)
+        .chain(dependents.into_iter().flatten().map(String::as_str))
+        .collect::<Vec<_>>();
+    // the API takes up to 50 titles at once
+    for chunk in pages.chunks(50) {
+        if let Err(e) = crate::wp::purge(&client, &chunk.join("|"), true).await {
+            warn!(?e, %wiki, %title, "couldn't purge");
+        }
+    }
+}
+
+/// Let the repo know how deploying the push to a page went, as a commit status on the pushed
+/// commit and optionally a deployment. Needs a GitHub token with access to commit statuses.
+async fn report_status(
+    ss: &SharedState,
+    push: &GitHubPush,
+    wiki: &str,
+    title: &str,
+    state: &str,
+    description: &str,
+    target_url: Option<&str>,
+) {
+    if ss.github_token.is_none() || !ss.config().commit_statuses {
+        return;
+    }
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return;
+    };
+    let context = format!("usync/{wiki}: {title}");
+    if let Err(e) = create_status(
+        ss,
+        repo,
+        &push.after,
+        state,
+        &context,
+        description,
+        target_url,
+    )
+    .await
+    {
+        warn!(?e, %wiki, %title, "couldn't report commit status");
+    }
+
+    if ss.config().deployments {
+        let page = index_url(ss, wiki, &[("title", title)]);
+        let deployment = Deployment {
+            environment: &context,
+            environment_url: &page,
+            state,
+            description,
+            log_url: target_url,
+        };
+        if let Err(e) = create_deployment(ss, repo, &push.after, &deployment).await {
+            warn!(?e, %wiki, %title, "couldn't record deployment");
+        }
+    }
+}
+
+/// A link to `index.php` on a wiki with the given query.
+pub fn index_url(ss: &SharedState, wiki: &str, query: &[(&str, &str)]) -> String {
+    let index = ss.config().wikis[wiki].api.replace("api.php", "index.php");
+    match reqwest::Url::parse_with_params(&index, query) {
+        Ok(url) => url.into(),
+        Err(_) => index,
+    }
+}
+
+/// Comment on the pushed commit with a link to the deployed page.
+async fn comment_deployed(
+    ss: &SharedState,
+    push: &GitHubPush,
+    wiki: &str,
+    title: &str,
+    diff: Option<&str>,
+) {
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return;
+    };
+    let page = index_url(ss, wiki, &[("title", title)]);
+    let mut body = format!("Deployed to [{title}]({page}) on {wiki}");
+    if let Some(diff) = diff {
+        body += &format!(" ([diff]({diff}))");
+    }
+    if let Err(e) = create_commit_comment(ss, repo, &push.after, &body).await {
+        warn!(?e, %wiki, %title, "couldn't comment on commit");
+    }
+}
+
+/// A changelog line for a deployment, linking the commit and quoting its message.
+fn changelog_line(date: &str, repo_url: &str, sha: &str, message: &str, more: usize) -> String {
+    let short = &sha[..sha.len().min(7)];
+    // commit messages can't close the nowiki early
+    let message = message.lines().next().unwrap_or("").replace('<', "&lt;");
+    let mut line =
+        format!("* {date}: [{repo_url}/commit/{sha} {short}] <nowiki>{message}</nowiki>");
+    if more > 0 {
+        line += &format!(" (and {more} more)");
+    }
+    line
+}
+
+/// Add a line for the deployment to the page's changelog subpage.
+async fn append_changelog(ss: &SharedState, push: &GitHubPush, wiki: &str, page: &str) {
+    let head = push.head_commit.as_ref().or(push.commits.last());
+    let message = head.map_or("", |c| &*c.message);
+    let more = push.commits.len().saturating_sub(1);
+    let line = changelog_line(
+        &expand::today(),
+        &push.repository.html_url,
+        &push.after,
+        message,
+        more,
+    );
+    let summary = edit_summary(ss, wiki, push);
+    let res = crate::wp::append(&ss.client(wiki), page, &format!("\n{line}"), &summary).await;
+    if let Err(e) = res {
+        warn!(?e, %wiki, %page, "couldn't update changelog");
+    }
+}
+
+#[test]
+fn test_changelog_line() {
+    assert_eq!(
+        changelog_line(
+            "2026-10-15",
+            "https://github.com/fee1-dead/usync",
+            "0123456789abcdef",
+            "Fix <b>\n\nlonger description",
+            0
+        ),
+        "* 2026-10-15: [https://github.com/fee1-dead/usync/commit/0123456789abcdef 0123456] \
+        <nowiki>Fix &lt;b></nowiki>"
+    );
+    assert!(changelog_line("", "", "abc", "Fix", 2).ends_with(" (and 2 more)"));
+}
+
+const GONE: &str = "the page no longer exists on the wiki";
+const BLOCKED: &str = "syncing this page is blocked by an admin";
+
+/// How a sync ended, once retries are over.
+enum Finish {
+    Deployed {
+        revid: Option<u64>,
+        comment: bool,
+        purge: bool,
+        changelog: Option<String>,
+        summary: String,
+    },
+    UpToDate,
+    Skipped,
+    Drifted,
+    Gone,
+    Removed,
+    Held(String),
+    Blocked,
+    /// the page or the file is at fault
+    Failed(String),
+    /// we couldn't get the sync done
+    Errored(String),
+}
+
+/// Sync a single title, holding off while its wiki is read-only.
+/// Returns whether the page is now in sync.
+#[instrument(skip_all, fields(sync_id = %push.sync_id, %wiki, title = %target.title))]
+async fn sync(ss: Arc<SharedState>, push: GitHubPush, wiki: String, target: Target) -> bool {
+    let title = target.title.clone();
+    publish(
+        &ss,
+        Activity::SyncStarted {
+            sync_id: push.sync_id.clone(),
+            wiki: wiki.clone(),
+            title: title.clone(),
+        },
+    );
+    let mut backoff = READONLY_BACKOFF;
+    let mut github_retries = RATELIMIT_RETRIES;
+    let mut conflict_retries = CONFLICT_RETRIES;
+    let fetch_retries = AtomicU32::new(0);
+    let finish = loop {
+        // don't even try while a read-only pause is in effect
+        let paused_until = ss.paused_until.lock().unwrap().get(&wiki).copied();
+        if let Some(until) = paused_until {
+            tokio::time::sleep_until(until).await;
+        }
+
+        let res = {
+            let metrics = &ss.metrics;
+            metrics.syncs_queued.fetch_add(1, Ordering::Relaxed);
+            let permit = ss.sync_permits.acquire(Tier::of(&push)).await;
+            metrics.syncs_queued.fetch_sub(1, Ordering::Relaxed);
+            metrics.syncs_running.fetch_add(1, Ordering::Relaxed);
+            let res = tokio::time::timeout(
+                Duration::from_secs(ss.config().sync_timeout_secs),
+                sort(
+                    ss.clone(),
+                    push.clone(),
+                    wiki.clone(),
+                    target.clone(),
+                    &fetch_retries,
+                ),
+            )
+            .await;
+            metrics.syncs_running.fetch_sub(1, Ordering::Relaxed);
+            drop(permit);
+            res
+        };
+
+        match res {
+            Ok(Outcome::Deployed { nochange: true, .. }) => break Finish::UpToDate,
+            Ok(Outcome::Deployed {
+                revid,
+                nochange: false,
+                comment,
+                purge,
+                changelog,
+                summary,
+            }) => {
+                break Finish::Deployed {
+                    revid,
+                    comment,
+                    purge,
+                    changelog,
+                    summary,
+                };
+            }
+            Ok(Outcome::Done) => break Finish::Skipped,
+            Ok(Outcome::Drifted) => break Finish::Drifted,
+            Ok(Outcome::Gone) => break Finish::Gone,
+            Ok(Outcome::Removed) => break Finish::Removed,
+            Ok(Outcome::Held(reason)) => break Finish::Held(reason),
+            Ok(Outcome::Blocked) => break Finish::Blocked,
+            Ok(Outcome::Failed(reason)) => break Finish::Failed(reason),
+            Ok(Outcome::ReadOnly) => {
+                warn!(%wiki, %title, ?backoff, "pausing edits until the wiki is writable");
+                let until = Instant::now() + backoff;
+                let mut paused_until = ss.paused_until.lock().unwrap();
+                let paused_until = paused_until.entry(wiki.clone()).or_insert(until);
+                *paused_until = (*paused_until).max(until);
+                backoff = (backoff * 2).min(READONLY_BACKOFF_MAX);
+            }
+            Ok(Outcome::RateLimited(retry_after)) => {
+                if retry_after > RATELIMIT_WAIT_MAX || github_retries == 0 {
+                    error!(%wiki, %title, ?retry_after, "rate limited by github, giving up");
+                    break Finish::Errored("rate limited by GitHub".to_owned());
+                }
+                warn!(%wiki, %title, ?retry_after, "rate limited by github, retrying");
+                github_retries -= 1;
+                tokio::time::sleep(retry_after).await;
+            }
+            Ok(Outcome::Conflicted) => {
+                if conflict_retries == 0 {
+                    error!(%wiki, %title, "page keeps being edited, giving up");
+                    break Finish::Errored("edit conflict".to_owned());
+                }
+                info!(%wiki, %title, "edit conflict, starting over");
+                conflict_retries -= 1;
+            }
+            Err(Elapsed { .. }) => {
+                error!(%wiki, %title, "task timed out!");
+                break Finish::Errored("timed out".to_owned());
+            }
+        }
+    };
+
+    // a staged deployment only touched the staging page, it's recorded and reported as such
+    let staged = match &finish {
+        Finish::Deployed { .. } => ss.config().staging.redirect(&wiki, &title),
+        _ => None,
+    };
+    let (result, revid, error) = match &finish {
+        Finish::Deployed { revid, .. } if staged.is_some() => ("staged", *revid, None),
+        Finish::Deployed { revid, .. } => ("deployed", *revid, None),
+        Finish::UpToDate => ("nochange", None, None),
+        Finish::Skipped => ("skipped", None, None),
+        Finish::Drifted => ("drifted", None, None),
+        Finish::Gone => ("gone", None, Some(GONE)),
+        Finish::Removed => ("removed", None, None),
+        Finish::Held(reason) => ("held", None, Some(&**reason)),
+        Finish::Blocked => ("blocked", None, Some(BLOCKED)),
+        Finish::Failed(reason) => ("failure", None, Some(&**reason)),
+        Finish::Errored(reason) => ("error", None, Some(&**reason)),
+    };
+    let ok = !matches!(
+        finish,
+        Finish::Failed(_) | Finish::Errored(_) | Finish::Drifted | Finish::Gone | Finish::Held(_)
+    );
+    // a dry run leaves nothing behind but the log
+    if push.dry_run {
+        info!(%wiki, %title, result, ?error, "dry run finished");
+        return ok;
+    }
+    let summary = match &finish {
+        Finish::Deployed { summary, .. } => Some(summary.clone()),
+        _ => None,
+    };
+    let (recorded_wiki, recorded_title) = match &staged {
+        Some((wiki, title)) => (wiki, title),
+        None => (&wiki, &title),
+    };
+    let attempt = Attempt {
+        wiki: recorded_wiki,
+        repo: &push.repository.html_url,
+        ref_: &push.ref_,
+        path: &target.path,
+        title: recorded_title,
+        sha: &push.after,
+        sync_id: &push.sync_id,
+        result,
+        revid,
+        error,
+        summary: summary.as_deref(),
+        fetch_retries: fetch_retries.into_inner(),
+    };
+    if let Err(e) = ss.audit.record(&attempt) {
+        warn!(?e, %wiki, %title, "couldn't record sync attempt");
+    }
+    if let Finish::Failed(reason) | Finish::Errored(reason) | Finish::Held(reason) = &finish {
+        if let Err(e) = ss.audit.add_failed(&wiki, &target, &push, reason) {
+            warn!(?e, %wiki, %title, "couldn't keep failed sync");
+        }
+        publish(
+            &ss,
+            Activity::SyncFailed {
+                sync_id: push.sync_id.clone(),
+                wiki: wiki.clone(),
+                title: title.clone(),
+                reason: reason.clone(),
+            },
+        );
+    }
+    // retrying won't bring the page back, so it isn't kept as failed
+    if let Finish::Gone = &finish {
+        publish(
+            &ss,
+            Activity::SyncFailed {
+                sync_id: push.sync_id.clone(),
+                wiki: wiki.clone(),
+                title: title.clone(),
+                reason: GONE.to_owned(),
+            },
+        );
+    }
+    if let Finish::Deployed { revid, .. } = &finish {
+        publish(
+            &ss,
+            Activity::EditSaved {
+                sync_id: push.sync_id.clone(),
+                wiki: recorded_wiki.clone(),
+                title: recorded_title.clone(),
+                revid: *revid,
+            },
+        );
+    }
+
+    if let Finish::Drifted = &finish {
+        publish(
+            &ss,
+            Activity::DriftDetected {
+                sync_id: push.sync_id.clone(),
+                wiki: wiki.clone(),
+                title: title.clone(),
+                sha: push.after.clone(),
+            },
+        );
+    }
+
+    match finish {
+        Finish::Deployed {
+            revid,
+            comment,
+            purge,
+            changelog,
+            ..
+        } => {
+            info!(%wiki, %title, ?revid, "deployed");
+            let (edited_wiki, edited_title) =
+                staged.clone().unwrap_or((wiki.clone(), title.clone()));
+            let commit_url = format!("{}/commit/{}", push.repository.html_url, push.after);
+            let lang = ss.config().wikis[&wiki].language.clone();
+            let summary = parse_webhook(push.clone()).into_edit_summary("", &lang);
+            log_deploy(&ss, &edited_wiki, &edited_title, &commit_url, &summary);
+            let diff =
+                revid.map(|revid| index_url(&ss, &edited_wiki, &[("diff", &revid.to_string())]));
+            // the real page wasn't deployed to, GitHub shouldn't hear that it was
+            if staged.is_none() {
+                report_status(
+                    &ss,
+                    &push,
+                    &wiki,
+                    &title,
+                    "success",
+                    "deployed",
+                    diff.as_deref(),
+                )
+                .await;
+                if comment {
+                    comment_deployed(&ss, &push, &wiki, &title, diff.as_deref()).await;
+                }
+            }
+            if let Some(subpage) = changelog {
+                let page = format!("{edited_title}/{subpage}");
+                append_changelog(&ss, &push, &edited_wiki, &page).await;
+            }
+            if purge {
+                let config = ss.config();
+                let dependents = match staged {
+                    Some(_) => None,
+                    None => config.wikis[&wiki].dependents.get(&title),
+                };
+                purge_deployed(&ss, &edited_wiki, &edited_title, dependents).await;
+            }
+            // loaders and gadget definitions point at the real page, which staging left alone
+            if staged.is_none() {
+                refresh_loaders(&ss, &wiki, &title).await;
+                gadgets::deployed(&ss, &wiki, &title, &push.after).await;
+            }
+        }
+        Finish::UpToDate => {
+            info!(%wiki, %title, "edit made no change");
+            let description = "already up to date";
+            report_status(&ss, &push, &wiki, &title, "success", description, None).await;
+        }
+        Finish::Skipped | Finish::Drifted => {}
+        Finish::Gone => {
+            report_status(&ss, &push, &wiki, &title, "failure", GONE, None).await;
+        }
+        Finish::Removed => {
+            let description = "the file was removed, so the page is no longer synced";
+            report_status(&ss, &push, &wiki, &title, "success", description, None).await;
+        }
+        Finish::Held(reason) => {
+            report_status(&ss, &push, &wiki, &title, "pending", &reason, None).await;
+        }
+        Finish::Blocked => {
+            report_status(&ss, &push, &wiki, &title, "error", BLOCKED, None).await;
+        }
+        Finish::Failed(reason) => {
+            report_status(&ss, &push, &wiki, &title, "failure", &reason, None).await;
+        }
+        Finish::Errored(reason) => {
+            report_status(&ss, &push, &wiki, &title, "error", &reason, None).await;
+        }
+    }
+    ok
+}
+
+/// Run a sync again, e.g. one that failed before.
+pub fn retry(ss: Arc<SharedState>, push: GitHubPush, wiki: String, target: Target) {
+    tokio::spawn(async move {
+        let fut = sync(ss.clone(), push.clone(), wiki, target);
+        guarded(&ss, &push, fut).await;
+    });
+}
+
+/// Run work for a push, catching a panic so it's logged, counted and kept in the audit log
+/// rather than disappearing with the task.
+async fn guarded<T>(
+    ss: &SharedState,
+    push: &GitHubPush,
+    fut: impl Future<Output = T>,
+) -> Option<T> {
+    let panic = match AssertUnwindSafe(fut).catch_unwind().await {
+        Ok(out) => return Some(out),
+        Err(panic) => panic,
+    };
+    let message = panic_message(&*panic);
+    let repo = repo_name(&push.repository.html_url).unwrap_or(&push.repository.html_url);
+    error!(sync_id = %push.sync_id, %repo, ref_ = %push.ref_, after = %push.after, %message, "sync panicked");
+    ss.metrics.sync_panics.fetch_add(1, Ordering::Relaxed);
+    if let Err(e) = ss.audit.add_panic(push, &message) {
+        warn!(?e, sync_id = %push.sync_id, "couldn't record panic");
+    }
+    None
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> String {
+    if let Some(s) = panic.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = panic.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "unknown panic".to_string()
+    }
+}
+
+#[test]
+fn test_panic_message() {
+    let panic = std::panic::catch_unwind(|| panic!("static")).unwrap_err();
+    assert_eq!(panic_message(&*panic), "static");
+    let panic = std::panic::catch_unwind(|| panic!("formatted {}", 1)).unwrap_err();
+    assert_eq!(panic_message(&*panic), "formatted 1");
+    let panic = std::panic::catch_unwind(|| std::panic::panic_any(1)).unwrap_err();
+    assert_eq!(panic_message(&*panic), "unknown panic");
+}
+
+/// Deploy a mapped page from its branch head, even if we think it's up to date.
+pub async fn force_sync(ss: Arc<SharedState>, source: SyncSource, target: Target) -> bool {
+    let paths = vec![target.path.clone()];
+    let Some(push) = branch_push(&ss, &source.repo, &source.ref_, paths, None).await else {
+        return false;
+    };
+    // skip the "unchanged since last sync" shortcut
+    ss.etags
+        .lock()
+        .unwrap()
+        .remove(&(source.wiki.clone(), target.title.clone()));
+    let fut = sync(ss.clone(), push.clone(), source.wiki, target);
+    guarded(&ss, &push, fut).await.unwrap_or(false)
+}
+
+/// The pages a push could deploy to, going by the current map.
+pub fn targets(ss: &SharedState, push: &GitHubPush) -> Vec<(String, Target)> {
+    let map = ss.map.load();
+    ss.config()
+        .wikis
+        .keys()
+        .filter_map(|wiki| {
+            let targets = map.get(&SyncSource {
+                wiki: wiki.clone(),
+                repo: push.repository.html_url.clone(),
+                ref_: push.ref_.clone(),
+            })?;
+            Some(targets.iter().map(|t| (wiki.clone(), t.clone())))
+        })
+        .flatten()
+        .collect()
+}
+
+/// Deploy a push to those of its targets it touches.
+/// Returns whether all of them are now in sync.
+pub async fn deploy(
+    ss: Arc<SharedState>,
+    mut push: GitHubPush,
+    targets: Vec<(String, Target)>,
+) -> bool {
+    if push.changed.is_none() && push.maybe_truncated() {
+        push.changed = changed_files(&ss, &push).await;
+    }
+
+    let mut translations = Vec::new();
+    let mut docs = Vec::new();
+    let mut gated = true;
+    let subpages = push.run.is_none() && !push.dry_run;
+    for (wiki, target) in &targets {
+        let i18n = target.i18n.as_ref().is_some_and(|d| push.touches_dir(d));
+        let doc = target.doc.as_ref().is_some_and(|d| push.touches(d));
+        if !subpages || !i18n && !doc {
+            continue;
+        }
+        match subpage_gates(&ss, &push, wiki, target).await {
+            Ok(()) => {}
+            Err(Outcome::Done | Outcome::Blocked) => continue,
+            Err(_) => {
+                gated = false;
+                continue;
+            }
+        }
+        if i18n {
+            translations.push(i18n::sync(
+                ss.clone(),
+                push.clone(),
+                wiki.clone(),
+                target.clone(),
+            ));
+        }
+        if doc {
+            docs.push(docs::sync(
+                ss.clone(),
+                push.clone(),
+                wiki.clone(),
+                target.clone(),
+            ));
+        }
+    }
+
+    // skip pages whose file wasn't touched, no need to fetch them at all
+    let targets = targets
+        .into_iter()
+        .filter(|(_, t)| match &push.run {
+            Some(_) => t.artifact.is_some(),
+            None => push.touches(&t.path),
+        })
+        .collect::<Vec<_>>();
+    prefetch(&ss, &targets).await;
+    let tasks = targets
+        .into_iter()
+        .map(|(wiki, target)| coalesced(ss.clone(), push.clone(), wiki, target));
+
+    let (synced, translated, documented) =
+        tokio::join!(join_all(tasks), join_all(translations), join_all(docs));
+    gated
+        && synced
+            .into_iter()
+            .chain(translated)
+            .chain(documented)
+            .all(|ok| ok)
+}
+
+/// Check a page's header before syncing its translations or documentation, the way `sort`
+/// does before deploying the page itself. Fails with the outcome if the push may not touch its
+/// subpages.
+#[instrument(skip_all, fields(sync_id = %push.sync_id, %wiki, title = %target.title))]
+async fn subpage_gates(
+    ss: &SharedState,
+    push: &GitHubPush,
+    wiki: &str,
+    target: &Target,
+) -> Result<(), Outcome> {
+    check_blocked(ss, push, wiki, &target.title)?;
+    if push.skips_sync() {
+        info!("skipped by commit message");
+        return Err(Outcome::Done);
+    }
+    let Some(repo) = repo_name(&push.repository.html_url) else {
+        return Err(Outcome::Done);
+    };
+    let revision = match crate::wp::fetch(&ss.client(wiki), &target.title).await {
+        Ok(revision) => revision,
+        Err(e) => {
+            error!(?e, "couldn't fetch");
+            return Err(Outcome::Failed("couldn't fetch the wiki page".into()));
+        }
+    };
+    let MainSlot {
+        content,
+        contentmodel,
+    } = &revision.slots.main;
+    let Some(header) = parse_header(contentmodel, content) else {
+        error!("couldn't parse on-wiki header");
+        return Err(Outcome::Failed(
+            "couldn't parse the header on the wiki page".into(),
+        ));
+    };
+    if push.ref_ != header.ref_ || push.repository.html_url != ss.canonical_repo(&header.repo) {
+        error!("page syncs from a different source");
+        return Err(Outcome::Failed(
+            "the wiki page syncs from a different source".into(),
+        ));
+    }
+    header_gates(ss, push, repo, &header).await
+}
+
+/// How long a page fetched ahead of its sync can stand in for fetching it again.
+const PREFETCH_TTL: Duration = Duration::from_secs(30);
+
+/// Fetch the pages a push fans out to in batches, rather than with a request per page when
+/// each is synced.
+async fn prefetch(ss: &SharedState, targets: &[(String, Target)]) {
+    let mut by_wiki = HashMap::<&str, Vec<&str>>::new();
+    for (wiki, target) in targets {
+        by_wiki.entry(wiki).or_default().push(&target.title);
+    }
+    for (wiki, titles) in by_wiki {
+        // a single page might as well be fetched when it's synced
+        if titles.len() < 2 {
+            continue;
+        }
+        let pages = match crate::wp::fetch_many(&ss.client(wiki), &titles).await {
+            Ok(pages) => pages,
+            Err(e) => {
+                warn!(?e, %wiki, "couldn't fetch pages in a batch");
+                continue;
+            }
+        };
+        let now = Instant::now();
+        let mut prefetched = ss.prefetched.lock().unwrap();
+        prefetched.retain(|_, (at, _)| at.elapsed() < PREFETCH_TTL);
+        for (title, page) in pages {
+            prefetched.insert((wiki.to_owned(), title), (now, page));
+        }
+    }
+}
+
+/// Pushes waiting out the coalescing window by wiki and title, with a channel for the
+/// result so pushes folded into them can wait for it too.
+pub type Pending = HashMap<(String, String), (GitHubPush, Arc<watch::Sender<Option<bool>>>)>;
+
+/// Sync after waiting a little for more pushes to the same page, so a burst of them makes
+/// one edit. A push folded into one already waiting returns with that one's result.
+async fn coalesced(ss: Arc<SharedState>, push: GitHubPush, wiki: String, target: Target) -> bool {
+    let window = Duration::from_secs(ss.config().coalesce_secs);
+    if window.is_zero() {
+        return sync(ss, push, wiki, target).await;
+    }
+
+    let key = (wiki.clone(), target.title.clone());
+    let (done, folded) = {
+        let mut pending = ss.pending.lock().unwrap();
+        match pending.get_mut(&key) {
+            Some((waiting, done)) => {
+                info!(sync_id = %push.sync_id, into = %waiting.sync_id, %wiki, title = %target.title, "coalescing push");
+                waiting.merge(push);
+                (done.clone(), true)
+            }
+            None => {
+                let done = Arc::new(watch::Sender::new(None));
+                pending.insert(key.clone(), (push, done.clone()));
+                (done, false)
+            }
+        }
+    };
+    if folded {
+        let mut done = done.subscribe();
+        return match done.wait_for(Option::is_some).await {
+            Ok(ok) => ok.unwrap_or(false),
+            Err(_) => false,
+        };
+    }
+
+    tokio::time::sleep(window).await;
+    let Some((push, _)) = ss.pending.lock().unwrap().remove(&key) else {
+        return false;
+    };
+    let ok = sync(ss, push, wiki, target).await;
+    done.send_replace(Some(ok));
+    ok
+}
+
+/// Ask the compare API for every file a push changed.
+async fn changed_files(ss: &SharedState, push: &GitHubPush) -> Option<Vec<String>> {
+    let repo = repo_name(&push.repository.html_url)?;
+    match compare_files(ss, repo, &push.before, &push.after).await {
+        Ok(files) => Some(files),
+        Err(e) => {
+            warn!(?e, "couldn't compare commits, going by the payload");
+            None
+        }
+    }
+}
+
+pub async fn task(mut cx: Context) {
+    while let Some(mut push) = cx.recv.recv().await {
+        debug!(sync_id = %push.sync_id, ?push, "got task");
+        // we must already know of an on-wiki sync file with the given repo and reference
+        let targets = targets(&cx.ss, &push);
+
+        debug!(?targets, "targets");
+
+        if targets.is_empty() {
+            info!(sync_id = %push.sync_id, "no title obtained");
+
+            // make sure that we don't keep retrying in a loop.
+            if push.retry {
+                finish(&cx.ss, &push.sync_id);
+                continue;
+            }
+
+            // subscribe before asking, so we can't miss the rebuild
+            let mut rebuilt = cx.ss.rebuilt.subscribe();
+            // a full queue already has a rebuild coming, don't hold up other pushes for it
+            if let Err(TrySendError::Closed(_)) = cx.reparse_request.try_send(Reparse::All) {
+                error!("cannot send to parser!");
+            }
+
+            // send the push event back for a retry once the new map is in place.
+            push.retry = true;
+            let sender = cx.send.clone();
+            tokio::spawn(async move {
+                let _ = tokio::time::timeout(Duration::from_secs(60), rebuilt.changed()).await;
+                let _ = sender.send(push).await;
+            });
+
+            continue;
+        }
+
+        let ss = cx.ss.clone();
+        tokio::spawn(async move {
+            let sync_id = push.sync_id.clone();
+            // a panicking push is kept in the audit log, not replayed on restart
+            let fut = deploy(ss.clone(), push.clone(), targets);
+            guarded(&ss, &push, fut).await;
+            finish(&ss, &sync_id);
+        });
+    }
+}
+
+/// Done with a push, whether or not it deployed. Failed syncs are kept separately.
+fn finish(ss: &SharedState, sync_id: &str) {
+    if let Err(e) = ss.audit.finish(sync_id) {
+        warn!(?e, %sync_id, "couldn't clear journaled push");
+    }
+}
+
+pub fn start(cx: Context) -> JoinHandle<()> {
+    tokio::spawn(task(cx))
+}
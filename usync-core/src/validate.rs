@@ -0,0 +1,202 @@
+//! Checks on files before they are deployed, so a broken file doesn't break everyone loading it.
+
+use cssparser::{ParseError, ParserInput, Token};
+use lightningcss::stylesheet::{ParserOptions, StyleSheet};
+use oxc_allocator::Allocator;
+use oxc_parser::Parser;
+use oxc_span::SourceType;
+
+/// 1-based line and column of a byte offset.
+pub(crate) fn position(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().unwrap_or("").chars().count() + 1;
+    (line, column)
+}
+
+/// Parse a script the way browsers load user scripts and gadgets, returning the first error.
+pub fn javascript(text: &str) -> Result<(), String> {
+    let allocator = Allocator::default();
+    let res = Parser::new(&allocator, text, SourceType::script()).parse();
+    let Some(error) = res.diagnostics.first() else {
+        return Ok(());
+    };
+    let offset = error.labels.first().map(|label| label.offset() as usize);
+    Err(match offset {
+        Some(offset) => {
+            let (line, column) = position(text, offset);
+            format!("{error} at line {line}, column {column}")
+        }
+        None => error.to_string(),
+    })
+}
+
+/// Parse a stylesheet, returning the first error.
+pub fn css(text: &str) -> Result<(), String> {
+    match StyleSheet::parse(text, ParserOptions::default()) {
+        Ok(_) => Ok(()),
+        Err(e) => Err(match e.loc {
+            Some(loc) => format!("{} at line {}, column {}", e.kind, loc.line + 1, loc.column),
+            None => e.kind.to_string(),
+        }),
+    }
+}
+
+/// Parse a Scribunto module as Lua 5.1, returning the first error.
+pub fn lua(text: &str) -> Result<(), String> {
+    let Err(errors) = full_moon::parse(text) else {
+        return Ok(());
+    };
+    let Some(error) = errors.first() else {
+        return Ok(());
+    };
+    let (start, _) = error.range();
+    Err(format!(
+        "{} at line {}, column {}",
+        error.error_message(),
+        start.line(),
+        start.character()
+    ))
+}
+
+/// Parse a JSON file and check it against a schema, if it has one.
+pub fn json(text: &str, schema: Option<&serde_json::Value>) -> Result<(), String> {
+    let value = serde_json::from_str::<serde_json::Value>(text).map_err(|e| e.to_string())?;
+    let Some(schema) = schema else {
+        return Ok(());
+    };
+    let validator =
+        jsonschema::validator_for(schema).map_err(|e| format!("invalid schema: {e}"))?;
+    match validator.iter_errors(&value).next() {
+        Some(e) => Err(format!("{e} at `{}`", e.instance_path())),
+        None => Ok(()),
+    }
+}
+
+/// Where TemplateStyles lets stylesheets load images from.
+const UPLOADS: &str = "//upload.wikimedia.org/wikipedia/";
+
+fn allowed_url(url: &str) -> bool {
+    url.strip_prefix("https:")
+        .unwrap_or(url)
+        .starts_with(UPLOADS)
+}
+
+/// Look for what TemplateStyles doesn't allow, through nested blocks.
+fn sanitized_tokens<'i>(
+    input: &mut cssparser::Parser<'i, '_>,
+) -> Result<(), ParseError<'i, String>> {
+    loop {
+        let token = match input.next() {
+            Ok(token) => token.clone(),
+            Err(_) => return Ok(()),
+        };
+        let refused = match &token {
+            Token::AtKeyword(name) => ["import", "charset", "namespace"]
+                .iter()
+                .any(|r| name.eq_ignore_ascii_case(r))
+                .then(|| format!("@{name} isn't allowed")),
+            Token::Ident(name)
+                if name.eq_ignore_ascii_case("behavior")
+                    || name.eq_ignore_ascii_case("-moz-binding") =>
+            {
+                Some(format!("{name} isn't allowed"))
+            }
+            Token::Function(name) if name.eq_ignore_ascii_case("expression") => {
+                Some("expression() isn't allowed".to_owned())
+            }
+            Token::UnquotedUrl(url) if !allowed_url(url) => {
+                Some(format!("images can only come from {UPLOADS}, not {url}"))
+            }
+            _ => None,
+        };
+        if let Some(reason) = refused {
+            return Err(input.new_custom_error(reason));
+        }
+
+        match &token {
+            Token::Function(name) if name.eq_ignore_ascii_case("url") => {
+                input.parse_nested_block(|input| {
+                    let url = input.expect_string()?.clone();
+                    if allowed_url(&url) {
+                        Ok(())
+                    } else {
+                        Err(input.new_custom_error(format!(
+                            "images can only come from {UPLOADS}, not {url}"
+                        )))
+                    }
+                })?;
+            }
+            Token::Function(_)
+            | Token::ParenthesisBlock
+            | Token::SquareBracketBlock
+            | Token::CurlyBracketBlock => input.parse_nested_block(sanitized_tokens)?,
+            _ => {}
+        }
+    }
+}
+
+/// Check a stylesheet against what TemplateStyles keeps, returning the first violation.
+pub fn sanitized_css(text: &str) -> Result<(), String> {
+    css(text)?;
+    let mut input = ParserInput::new(text);
+    let mut parser = cssparser::Parser::new(&mut input);
+    sanitized_tokens(&mut parser).map_err(|e| {
+        let reason = match e.kind {
+            cssparser::ParseErrorKind::Custom(reason) => reason,
+            cssparser::ParseErrorKind::Basic(e) => format!("{e:?}"),
+        };
+        format!(
+            "{reason} at line {}, column {}",
+            e.location.line + 1,
+            e.location.column
+        )
+    })
+}
+
+#[test]
+fn test_javascript() {
+    assert!(javascript("mw.hook('wikipage.content').add(function () {});").is_ok());
+    assert_eq!(
+        javascript("var a = 1;\nvar b = ;").unwrap_err(),
+        "Unexpected token at line 2, column 9"
+    );
+}
+
+#[test]
+fn test_css() {
+    assert!(css(".mw-body { color: red; }").is_ok());
+    assert!(css("}} .a { color: red; }").is_err());
+
+    assert!(
+        sanitized_css(".a { background: url(//upload.wikimedia.org/wikipedia/x.png) }").is_ok()
+    );
+    assert!(sanitized_css("@import url(x.css);").is_err());
+    assert!(sanitized_css(".a { background: url('https://example.org/x.png') }").is_err());
+    assert!(sanitized_css("@media screen { .a { behavior: url(x.htc) } }").is_err());
+}
+
+#[test]
+fn test_json() {
+    assert!(json("{\"a\": 1}", None).is_ok());
+    assert!(json("{\"a\": 1,}", None).is_err());
+
+    let schema = serde_json::json!({
+        "type": "object",
+        "properties": { "a": { "type": "integer" } },
+    });
+    assert!(json("{\"a\": 1}", Some(&schema)).is_ok());
+    assert_eq!(
+        json("{\"a\": \"b\"}", Some(&schema)).unwrap_err(),
+        "\"b\" is not of type \"integer\" at `/a`"
+    );
+}
+
+#[test]
+fn test_lua() {
+    assert!(lua("local p = {}\nfunction p.main(frame) return 1 end\nreturn p").is_ok());
+    assert_eq!(
+        lua("local p = {}\nfunction p.main( return").unwrap_err(),
+        "expected a parameter name or `)` at line 2, column 18"
+    );
+}
@@ -0,0 +1,487 @@
+use std::collections::HashMap;
+use std::fmt;
+
+use color_eyre::eyre::{Result, bail, eyre};
+use serde::Deserialize;
+use serde::de::DeserializeOwned;
+
+#[derive(Deserialize)]
+pub struct MainSlot {
+    pub content: String,
+    pub contentmodel: String,
+}
+
+#[derive(Deserialize)]
+pub struct Slots {
+    pub main: MainSlot,
+}
+
+#[derive(Deserialize)]
+pub struct Revision {
+    pub slots: Slots,
+    /// who made the revision, if asked for and not hidden
+    #[serde(default)]
+    pub user: Option<String>,
+    /// if asked for with `ids`, which `fetch` and friends do
+    #[serde(default)]
+    pub revid: u64,
+    /// when the revision was made, e.g. `2024-01-01T00:00:00Z`
+    #[serde(default)]
+    pub timestamp: String,
+}
+
+#[derive(Deserialize)]
+pub struct Page {
+    pub title: String,
+    #[serde(default)]
+    pub ns: i64,
+    /// the latest revision, if asked for and the page exists
+    #[serde(default)]
+    pub revisions: Vec<Revision>,
+    #[serde(default)]
+    pub missing: bool,
+    #[serde(default)]
+    pub invalid: bool,
+}
+
+/// The page asked for doesn't exist, e.g. because it was deleted.
+#[derive(Debug)]
+pub struct Missing {
+    pub title: String,
+}
+
+impl fmt::Display for Missing {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} doesn't exist", self.title)
+    }
+}
+
+impl std::error::Error for Missing {}
+
+impl Page {
+    /// The latest revision, failing with [`Missing`] if the page doesn't exist.
+    pub fn latest(self) -> Result<Revision> {
+        if self.missing || self.invalid {
+            return Err(Missing { title: self.title }.into());
+        }
+        let title = self.title;
+        self.revisions
+            .into_iter()
+            .next()
+            .ok_or_else(|| eyre!("no revisions of {title} in the response"))
+    }
+}
+
+/// A title the API answered for under another name, see [`Pages::resolve`].
+#[derive(Deserialize)]
+pub struct Rename {
+    pub from: String,
+    pub to: String,
+}
+
+#[derive(Deserialize)]
+pub struct Pages<P> {
+    pub pages: P,
+    /// titles with underscores, a lowercase first letter and the like
+    #[serde(default)]
+    pub normalized: Vec<Rename>,
+    /// redirects followed when asked for with `redirects`
+    #[serde(default)]
+    pub redirects: Vec<Rename>,
+}
+
+impl<P> Pages<P> {
+    /// The title a page asked for as `title` is listed under in the response.
+    pub fn resolve(&self, title: &str) -> String {
+        let mut title = title.to_owned();
+        for renames in [&self.normalized, &self.redirects] {
+            if let Some(r) = renames.iter().find(|r| r.from == title) {
+                title.clone_from(&r.to);
+            }
+        }
+        title
+    }
+}
+
+#[derive(Deserialize)]
+pub struct Response<P> {
+    pub query: Pages<P>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct ApiError {
+    pub code: String,
+    pub info: String,
+}
+
+impl fmt::Display for ApiError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {}", self.code, self.info)
+    }
+}
+
+impl std::error::Error for ApiError {}
+
+/// Send a query and read its response, turning an error from the API into an [`ApiError`]
+/// rather than a confusing deserialization failure.
+async fn read<T: DeserializeOwned>(req: reqwest::RequestBuilder) -> Result<T> {
+    let mut value = req
+        .send()
+        .await?
+        .error_for_status()?
+        .json::<serde_json::Value>()
+        .await?;
+    if let Some(error) = value.get_mut("error") {
+        return Err(serde_json::from_value::<ApiError>(error.take())?.into());
+    }
+    Ok(serde_json::from_value(value)?)
+}
+
+/// The one page a query for a single title returns.
+fn only<P>(pages: Vec<P>) -> Result<P> {
+    let mut pages = pages.into_iter();
+    match (pages.next(), pages.next()) {
+        (Some(page), None) => Ok(page),
+        _ => bail!("expected a single page in the response"),
+    }
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EditResult {
+    pub result: String,
+    #[serde(default)]
+    pub nochange: bool,
+    pub newrevid: Option<u64>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EditResponse {
+    pub edit: Option<EditResult>,
+    pub error: Option<ApiError>,
+}
+
+#[derive(Deserialize)]
+pub struct UserInfo {
+    pub name: String,
+    #[serde(default)]
+    pub anon: bool,
+    #[serde(default)]
+    pub rights: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UserInfoQuery {
+    userinfo: UserInfo,
+}
+
+#[derive(Deserialize)]
+struct UserInfoResponse {
+    query: UserInfoQuery,
+}
+
+pub type MultiPageResponse = Response<Vec<Page>>;
+
+/// Purge the cache of some pages.
+pub async fn purge(client: &w::Client, titles: &str, forcelinkupdate: bool) -> Result<()> {
+    let mut params = vec![("action", "purge"), ("titles", titles)];
+    if forcelinkupdate {
+        params.push(("forcelinkupdate", "1"));
+    }
+    client.post(&*params).send().await?.error_for_status()?;
+    Ok(())
+}
+
+/// Make an edit with `params` and a fresh token, trying once more if the token went bad in
+/// between, e.g. because the session was renewed.
+pub async fn edit(client: &w::Client, params: &[(&str, &str)]) -> Result<EditResponse> {
+    let mut retried = false;
+    loop {
+        let token = client.get_token("csrf").await?;
+        let mut params = params.to_vec();
+        params.push(("token", &token));
+        let res: EditResponse = client
+            .post(&*params)
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        match &res.error {
+            Some(e) if e.code == "badtoken" && !retried => retried = true,
+            _ => return Ok(res),
+        }
+    }
+}
+
+/// Like [`edit`], but for edits whose only interesting outcome is whether they failed.
+async fn edit_or_fail(client: &w::Client, params: &[(&str, &str)]) -> Result<()> {
+    match edit(client, params).await?.error {
+        Some(e) => Err(e.into()),
+        None => Ok(()),
+    }
+}
+
+/// Save a page without changing it, which re-renders it and bumps its touched timestamp.
+pub async fn null_edit(client: &w::Client, title: &str) -> Result<()> {
+    edit_or_fail(
+        client,
+        &[
+            ("action", "edit"),
+            ("title", title),
+            ("appendtext", ""),
+            ("nocreate", "1"),
+        ],
+    )
+    .await
+}
+
+/// Add a new section to the end of a page.
+pub async fn new_section(client: &w::Client, title: &str, section: &str, text: &str) -> Result<()> {
+    edit_or_fail(
+        client,
+        &[
+            ("action", "edit"),
+            ("title", title),
+            ("section", "new"),
+            ("sectiontitle", section),
+            ("text", text),
+        ],
+    )
+    .await
+}
+
+/// The content of a page, or `None` if it doesn't exist yet.
+pub async fn content(client: &w::Client, title: &str) -> Result<Option<String>> {
+    let r: MultiPageResponse = read(client.get([
+        ("action", "query"),
+        ("prop", "revisions"),
+        ("titles", title),
+        ("rvprop", "content|contentmodel"),
+        ("rvslots", "main"),
+    ]))
+    .await?;
+    let page = only(r.query.pages)?;
+    if page.missing {
+        return Ok(None);
+    }
+    Ok(Some(page.latest()?.slots.main.content))
+}
+
+/// Save a page, creating it if needed.
+pub async fn save(
+    client: &w::Client,
+    title: &str,
+    text: &str,
+    summary: &str,
+) -> Result<EditResponse> {
+    edit(
+        client,
+        &[
+            ("action", "edit"),
+            ("title", title),
+            ("text", text),
+            ("summary", summary),
+            ("bot", "1"),
+        ],
+    )
+    .await
+}
+
+/// Like [`save`], but fails with an `editconflict` error if the page changed since `base`.
+pub async fn save_over(
+    client: &w::Client,
+    title: &str,
+    text: &str,
+    summary: &str,
+    base: &Revision,
+) -> Result<EditResponse> {
+    edit(
+        client,
+        &[
+            ("action", "edit"),
+            ("title", title),
+            ("text", text),
+            ("summary", summary),
+            ("bot", "1"),
+            ("baserevid", &base.revid.to_string()),
+            ("basetimestamp", &base.timestamp),
+            ("nocreate", "1"),
+        ],
+    )
+    .await
+}
+
+/// Add text to the end of a page, creating it if needed.
+pub async fn append(client: &w::Client, title: &str, text: &str, summary: &str) -> Result<()> {
+    edit_or_fail(
+        client,
+        &[
+            ("action", "edit"),
+            ("title", title),
+            ("appendtext", text),
+            ("summary", summary),
+            ("bot", "1"),
+        ],
+    )
+    .await
+}
+
+/// Who we are logged in as, and what we may do.
+pub async fn user_info(client: &w::Client) -> Result<UserInfo> {
+    let r: UserInfoResponse = read(client.get([
+        ("action", "query"),
+        ("meta", "userinfo"),
+        ("uiprop", "rights"),
+    ]))
+    .await?;
+    Ok(r.query.userinfo)
+}
+
+#[derive(Deserialize)]
+struct MaybeMissing {
+    #[serde(default)]
+    missing: bool,
+    #[serde(default)]
+    invalid: bool,
+}
+
+/// Whether a page exists, looked up by `titles` or `pageids`.
+pub async fn exists(client: &w::Client, by: &str, page: &str) -> Result<bool> {
+    let r: Response<Vec<MaybeMissing>> =
+        read(client.get([("action", "query"), (by, page)])).await?;
+    let page = only(r.query.pages)?;
+    Ok(!page.missing && !page.invalid)
+}
+
+/// The talk page of a page, going by its namespace prefix.
+pub fn talk_page(title: &str) -> String {
+    match title.split_once(':') {
+        Some((ns, _)) if ns.ends_with(" talk") || ns == "Talk" => title.to_owned(),
+        Some((ns, rest)) => format!("{ns} talk:{rest}"),
+        None => format!("Talk:{title}"),
+    }
+}
+
+#[test]
+fn test_talk_page() {
+    assert_eq!(
+        talk_page("MediaWiki:Gadget-foo.js"),
+        "MediaWiki talk:Gadget-foo.js"
+    );
+    assert_eq!(talk_page("User:Foo/bar.js"), "User talk:Foo/bar.js");
+    assert_eq!(talk_page("User talk:Foo"), "User talk:Foo");
+    assert_eq!(talk_page("Foo"), "Talk:Foo");
+}
+
+#[test]
+fn test_page_latest() {
+    let r: MultiPageResponse = serde_json::from_str(
+        r#"{"query": {"pages": [{"ns": 2, "title": "User:A/b.js", "missing": true}]}}"#,
+    )
+    .unwrap();
+    let page = only(r.query.pages).unwrap();
+    assert!(page.missing);
+    assert!(page.latest().is_err_and(|e| e.is::<Missing>()));
+
+    let r: MultiPageResponse = serde_json::from_str(
+        r#"{"query": {"pages": [{"ns": 2, "title": "User:A/b.js", "revisions": [
+            {"revid": 5, "timestamp": "2024-01-01T00:00:00Z", "user": "A",
+             "slots": {"main": {"contentmodel": "javascript", "content": "x"}}}
+        ]}]}}"#,
+    )
+    .unwrap();
+    let rev = only(r.query.pages).unwrap().latest().unwrap();
+    assert_eq!(rev.revid, 5);
+    assert_eq!(rev.slots.main.content, "x");
+
+    assert!(only(Vec::<Page>::new()).is_err());
+}
+
+#[test]
+fn test_resolve() {
+    let r: MultiPageResponse = serde_json::from_str(
+        r#"{"query": {
+            "normalized": [{"from": "user:A/old_name.js", "to": "User:A/old name.js"}],
+            "redirects": [{"from": "User:A/old name.js", "to": "User:A/new.js"}],
+            "pages": []
+        }}"#,
+    )
+    .unwrap();
+    assert_eq!(r.query.resolve("user:A/old_name.js"), "User:A/new.js");
+    assert_eq!(r.query.resolve("User:A/old name.js"), "User:A/new.js");
+    assert_eq!(r.query.resolve("User:A/other.js"), "User:A/other.js");
+}
+
+/// The latest revision of a page, with its ID, timestamp and who made it.
+pub async fn fetch(client: &w::Client, title: &str) -> Result<Revision> {
+    fetch_page(client, title).await?.latest()
+}
+
+/// Pages by title, following redirects. Their titles are the canonical ones, which needn't be
+/// what was asked for.
+async fn fetch_pages(client: &w::Client, titles: &str) -> Result<Pages<Vec<Page>>> {
+    let r: MultiPageResponse = read(client.get([
+        ("action", "query"),
+        ("prop", "revisions"),
+        ("titles", titles),
+        ("redirects", "1"),
+        ("rvprop", "content|contentmodel|ids|timestamp|user"),
+        ("rvslots", "main"),
+    ]))
+    .await?;
+    Ok(r.query)
+}
+
+/// Most titles the API takes in a single query.
+pub const BATCH_SIZE: usize = 50;
+
+/// Many pages with their latest revisions, in as few requests as the API allows. They are keyed
+/// by the title asked for, so a page asked for under two names only turns up under one.
+pub async fn fetch_many(client: &w::Client, titles: &[&str]) -> Result<HashMap<String, Page>> {
+    let mut found = HashMap::new();
+    for chunk in titles.chunks(BATCH_SIZE) {
+        let r = fetch_pages(client, &chunk.join("|")).await?;
+        let resolved: Vec<_> = chunk.iter().map(|t| (*t, r.resolve(t))).collect();
+        let mut pages: HashMap<_, _> = r.pages.into_iter().map(|p| (p.title.clone(), p)).collect();
+        for (title, canonical) in resolved {
+            if let Some(page) = pages.remove(&canonical) {
+                found.insert(title.to_owned(), page);
+            }
+        }
+    }
+    Ok(found)
+}
+
+/// The latest revision of a page along with who made it.
+pub async fn fetch_page(client: &w::Client, title: &str) -> Result<Page> {
+    only(fetch_pages(client, title).await?.pages)
+}
+
+#[derive(Deserialize)]
+struct User {
+    #[serde(default)]
+    rights: Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct UsersQuery {
+    users: Vec<User>,
+}
+
+#[derive(Deserialize)]
+struct UsersResponse {
+    query: UsersQuery,
+}
+
+/// What another user may do, empty if there is no such user.
+pub async fn user_rights(client: &w::Client, name: &str) -> Result<Vec<String>> {
+    let r: UsersResponse = read(client.get([
+        ("action", "query"),
+        ("list", "users"),
+        ("ususers", name),
+        ("usprop", "rights"),
+    ]))
+    .await?;
+    // a user that doesn't exist comes back with `missing` and no rights
+    Ok(only(r.query.users)?.rights)
+}